@@ -0,0 +1,172 @@
+use std::fmt;
+
+use bevy::{
+    ecs::schedule::{NodeId, ScheduleGraph},
+    prelude::*,
+    utils::HashMap,
+};
+
+use crate::{
+    layer_graph::{DrawnNode, LayerGraph},
+    schedule_graph::ScheduleInfo,
+};
+
+/// What to show for a system or set in the graph, next to the code that adds it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GraphAnnotation {
+    /// Shown instead of its name
+    pub label: Option<String>,
+    /// Shown when hovering over it, like why it's ordered where it is
+    pub note: Option<String>,
+    /// Its colour when nothing else is highlighting it
+    pub color: Option<Color>,
+    /// For picking out related systems (A cycles through them)
+    pub tags: Vec<String>,
+}
+
+// For where there's only text, like the schedule check's output
+impl fmt::Display for GraphAnnotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(label) = &self.label {
+            parts.push(format!("label {:?}", label));
+        }
+        if let Some(note) = &self.note {
+            parts.push(format!("note {:?}", note));
+        }
+        if !self.tags.is_empty() {
+            parts.push(format!("tags {}", self.tags.join(", ")));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// Every annotation, by the set it was given for. A system is annotated through the set bevy makes
+/// for its type, so it applies to every copy of the system in every schedule. Boxed rather than
+/// interned so the sets in a schedule graph can be looked up by value
+#[derive(Resource, Debug, Default, PartialEq)]
+pub struct GraphAnnotations(pub HashMap<Box<dyn SystemSet>, GraphAnnotation>);
+
+impl Clone for GraphAnnotations {
+    fn clone(&self) -> Self {
+        GraphAnnotations(
+            self.0
+                .iter()
+                .map(|(set, annotation)| (set.dyn_clone(), annotation.clone()))
+                .collect(),
+        )
+    }
+}
+
+impl GraphAnnotations {
+    /// Each annotated node in a schedule, including the systems of annotated system types
+    pub fn nodes<'a>(&'a self, graph: &ScheduleGraph) -> Vec<(NodeId, &'a GraphAnnotation)> {
+        let mut nodes = Vec::new();
+        for (node, set, _) in graph.system_sets() {
+            let Some(annotation) = self.0.get(set) else {
+                continue;
+            };
+            nodes.push((node, annotation));
+            if set.system_type().is_some() {
+                let systems = graph.hierarchy().graph().neighbors(node);
+                nodes.extend(systems.map(|system| (system, annotation)));
+            }
+        }
+        nodes
+    }
+}
+
+pub trait AnnotateGraphExt {
+    fn annotate_system<M>(
+        &mut self,
+        system: impl IntoSystemSet<M>,
+        annotation: GraphAnnotation,
+    ) -> &mut Self;
+
+    fn annotate_set(&mut self, set: impl SystemSet, annotation: GraphAnnotation) -> &mut Self;
+}
+
+impl AnnotateGraphExt for App {
+    fn annotate_system<M>(
+        &mut self,
+        system: impl IntoSystemSet<M>,
+        annotation: GraphAnnotation,
+    ) -> &mut Self {
+        self.world
+            .get_resource_or_insert_with(GraphAnnotations::default)
+            .0
+            .insert(Box::new(system.into_system_set()), annotation);
+        self
+    }
+
+    fn annotate_set(&mut self, set: impl SystemSet, annotation: GraphAnnotation) -> &mut Self {
+        self.annotate_system(set, annotation)
+    }
+}
+
+/// Finds the nodes of a schedule that were annotated, and relabels them
+pub fn apply_annotations(
+    graph: &ScheduleGraph,
+    info: &mut ScheduleInfo,
+    annotations: &GraphAnnotations,
+) {
+    info.annotations.clear();
+    for (node, annotation) in annotations.nodes(graph) {
+        if let Some(label) = &annotation.label {
+            info.names.insert(node, label.clone());
+        }
+        info.annotations.insert(node, annotation.clone());
+    }
+}
+
+/// The tag whose nodes are highlighted in the graph
+#[derive(Resource, Debug, Default)]
+pub struct TagFilter {
+    pub tag: Option<String>,
+    pub filtered: Vec<DrawnNode>,
+}
+
+pub fn cycle_tag_filter(
+    keys: Res<ButtonInput<KeyCode>>,
+    info: Res<ScheduleInfo>,
+    mut filter: ResMut<TagFilter>,
+) {
+    if !keys.just_pressed(KeyCode::KeyA) {
+        return;
+    }
+    let mut tags: Vec<_> = info
+        .annotations
+        .values()
+        .flat_map(|annotation| &annotation.tags)
+        .collect();
+    tags.sort();
+    tags.dedup();
+    // Through each tag in turn and then back to none
+    let next = match &filter.tag {
+        None => tags.first(),
+        Some(tag) => tags.iter().skip_while(|t| **t != tag).nth(1),
+    };
+    filter.tag = next.map(|tag| tag.to_string());
+}
+
+pub fn filter_tagged_nodes(
+    mut filter: ResMut<TagFilter>,
+    layer_graph: Res<LayerGraph>,
+    info: Res<ScheduleInfo>,
+) {
+    if !filter.is_changed() && !layer_graph.is_changed() {
+        return;
+    }
+
+    let mut filtered = Vec::new();
+    if let Some(tag) = &filter.tag {
+        for (&node, annotation) in &info.annotations {
+            if annotation.tags.contains(tag) {
+                filtered.extend(layer_graph.displayed_as(&info, node));
+            }
+        }
+    }
+    if filter.filtered != filtered {
+        filter.filtered = filtered;
+    }
+}
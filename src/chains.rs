@@ -0,0 +1,252 @@
+use bevy::{
+    ecs::schedule::{NodeId, ScheduleBuildError},
+    prelude::*,
+    utils::{
+        petgraph::{graphmap::DiGraphMap, Direction},
+        HashSet,
+    },
+};
+
+use crate::{
+    graph_ui::GraphNode,
+    graph_utils::{check_graph, merge_nodes, topsort_graph},
+    layer_graph::DrawnNode,
+    schedule_graph::ScheduleInfo,
+};
+
+/// How runs of systems that just follow one another are drawn as one node
+#[derive(Resource, Debug, Clone, PartialEq, Eq)]
+pub struct ChainCompression {
+    /// Draw each chain as one node (V)
+    pub enabled: bool,
+    /// The fewest systems worth drawing as a chain
+    pub min_len: usize,
+    /// Chains clicked on to show their systems again, by their first system
+    pub expanded: HashSet<NodeId>,
+}
+
+impl Default for ChainCompression {
+    fn default() -> Self {
+        ChainCompression {
+            enabled: false,
+            min_len: 3,
+            expanded: HashSet::new(),
+        }
+    }
+}
+
+/// Merges each longest path of drawn systems where every system has only the one before it and
+/// the one after it (once the implied ordering is left out) into one node. Merging a path like
+/// that can't make a cycle, since nothing else can get from partway along it to partway along it
+pub fn compress_chains(
+    layering_graph: &mut DiGraphMap<DrawnNode, ()>,
+    info: &mut ScheduleInfo,
+    compression: &ChainCompression,
+) -> Result<(), ScheduleBuildError> {
+    if !compression.enabled {
+        return Ok(());
+    }
+    let topsort = topsort_graph(layering_graph)?;
+    let reduction = check_graph(layering_graph, &topsort).transitive_reduction;
+    let is_system = |node: DrawnNode| node.node().is_some_and(|node| node.is_system());
+    let only = |node: DrawnNode, direction: Direction| {
+        let mut neighbors = reduction.neighbors_directed(node, direction);
+        match (neighbors.next(), neighbors.next()) {
+            (Some(neighbor), None) if is_system(neighbor) => Some(neighbor),
+            _ => None,
+        }
+    };
+    // The system after this one in its chain
+    let next = |node: DrawnNode| {
+        only(node, Direction::Outgoing)
+            .filter(|&next| only(next, Direction::Incoming) == Some(node))
+    };
+
+    let mut chains = Vec::new();
+    for &node in &topsort {
+        // Chains are followed from their first system
+        if !is_system(node) || only(node, Direction::Incoming).and_then(next) == Some(node) {
+            continue;
+        }
+        let mut chain = vec![node];
+        while let Some(next) = next(*chain.last().unwrap()) {
+            chain.push(next);
+        }
+        let systems: Vec<_> = chain.iter().filter_map(|node| node.node()).collect();
+        if systems.len() >= compression.min_len.max(2)
+            && !compression.expanded.contains(&systems[0])
+        {
+            chains.push((chain, systems));
+        }
+    }
+
+    for (members, systems) in chains {
+        let chain = DrawnNode::Chain(info.chains.len());
+        merge_nodes(layering_graph, &members, chain);
+        for &system in &systems {
+            info.grouped_systems.insert(system, chain);
+        }
+        info.chains.push(systems);
+    }
+    Ok(())
+}
+
+pub fn chain_controls(keys: Res<ButtonInput<KeyCode>>, mut compression: ResMut<ChainCompression>) {
+    if keys.just_pressed(KeyCode::KeyV) {
+        // Compress everything again when turning it back on
+        compression.enabled = !compression.enabled;
+        compression.expanded.clear();
+    }
+}
+
+/// Clicking a chain's node shows its systems instead
+pub fn expand_chains(
+    keys: Res<ButtonInput<KeyCode>>,
+    nodes: Query<(&Interaction, &GraphNode), Changed<Interaction>>,
+    info: Res<ScheduleInfo>,
+    mut compression: ResMut<ChainCompression>,
+) {
+    // Modified clicks are for stepping
+    if keys.any_pressed([
+        KeyCode::ControlLeft,
+        KeyCode::ControlRight,
+        KeyCode::AltLeft,
+        KeyCode::AltRight,
+    ]) {
+        return;
+    }
+    for (interaction, node) in &nodes {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        if let DrawnNode::Chain(i) = node.0 {
+            compression.expanded.insert(info.chains[i][0]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schedule_graph::{schedule_graph_layout, LayoutSettings};
+
+    #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+    struct Between;
+
+    fn first() {}
+    fn second() {}
+    fn third() {}
+    fn fourth() {}
+    fn fifth() {}
+    fn sixth() {}
+    fn seventh() {}
+    fn eighth() {}
+
+    fn enabled(min_len: usize) -> ChainCompression {
+        ChainCompression {
+            enabled: true,
+            min_len,
+            ..default()
+        }
+    }
+
+    fn layout(app: &mut App, chains: &ChainCompression) -> ScheduleInfo {
+        app.update();
+        let schedule = app.world.resource::<Schedules>().get(Update).unwrap();
+        let settings = LayoutSettings {
+            chains: chains.clone(),
+            ..default()
+        };
+        schedule_graph_layout(schedule, &settings).unwrap().1
+    }
+
+    /// The systems in each chain that was drawn as one node
+    fn chains(app: &mut App, chains: &ChainCompression) -> Vec<Vec<String>> {
+        let info = layout(app, chains);
+        let mut chains: Vec<Vec<_>> = info
+            .chains
+            .iter()
+            .map(|chain| {
+                chain
+                    .iter()
+                    .map(|&system| info.name(system).to_string())
+                    .collect()
+            })
+            .collect();
+        chains.sort();
+        chains
+    }
+
+    #[test]
+    fn min_len() {
+        let mut app = App::new();
+        app.add_systems(Update, (first, second, third).chain());
+        assert_eq!(
+            chains(&mut app, &enabled(3)),
+            [["first", "second", "third"]]
+        );
+        assert!(chains(&mut app, &enabled(4)).is_empty());
+        assert!(chains(&mut app, &ChainCompression::default()).is_empty());
+
+        // Even a chain of one system is never drawn as one
+        let mut app = App::new();
+        app.add_systems(Update, (first, (second, third).chain()));
+        assert_eq!(chains(&mut app, &enabled(0)), [["second", "third"]]);
+    }
+
+    #[test]
+    fn branch_at_start() {
+        let mut app = App::new();
+        app.add_systems(
+            Update,
+            ((first, second, third, fourth).chain(), fifth.after(first)),
+        );
+        assert_eq!(
+            chains(&mut app, &enabled(3)),
+            [["second", "third", "fourth"]]
+        );
+    }
+
+    #[test]
+    fn branch_at_end() {
+        let mut app = App::new();
+        app.add_systems(
+            Update,
+            ((first, second, third, fourth).chain(), fifth.before(fourth)),
+        );
+        assert_eq!(
+            chains(&mut app, &enabled(3)),
+            [["first", "second", "third"]]
+        );
+    }
+
+    #[test]
+    fn expanded_chain() {
+        let mut app = App::new();
+        app.add_systems(Update, (first, second, third).chain());
+        let info = layout(&mut app, &enabled(3));
+        let mut compression = enabled(3);
+        compression.expanded.insert(info.chains[0][0]);
+
+        // Not even what's left of it after the first system is drawn as a chain
+        assert!(chains(&mut app, &compression).is_empty());
+    }
+
+    #[test]
+    fn chain_next_to_set() {
+        let mut app = App::new();
+        app.add_systems(
+            Update,
+            (
+                (first, second, third).chain().before(Between),
+                (fourth, fifth).in_set(Between),
+                (sixth, seventh, eighth).chain().after(Between),
+            ),
+        );
+        // The set ends one chain and starts the other, without being part of either
+        assert_eq!(
+            chains(&mut app, &enabled(3)),
+            [["first", "second", "third"], ["sixth", "seventh", "eighth"]]
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use bevy::{
+    ecs::schedule::NodeId,
+    prelude::*,
+    utils::{
+        petgraph::{graphmap::DiGraphMap, Direction},
+        HashMap, HashSet,
+    },
+};
+
+use crate::{
+    graph_ui::GraphNode,
+    graph_utils::{contract_node, find_cycles, merge_nodes, set_systems},
+    layer_graph::DrawnNode,
+    schedule_graph::ScheduleInfo,
+    shorten_type::SystemName,
+};
+
+/// How systems are grouped by the crate they come from when laying out the graph
+#[derive(Resource, Debug, Clone, Default, PartialEq, Eq)]
+pub struct CrateGrouping {
+    /// Collapse each crate's systems into one node (K)
+    pub enabled: bool,
+    /// Crates clicked on to show their systems again
+    pub expanded: HashSet<String>,
+    /// Leave bevy's own systems out completely (E)
+    pub hide_engine: bool,
+}
+
+/// A crate's systems, drawn as one node
+#[derive(Debug, Clone)]
+pub struct CrateGroup {
+    pub name: String,
+    pub systems: Vec<NodeId>,
+}
+
+pub fn is_engine_crate(name: &str) -> bool {
+    name == "bevy" || name.starts_with("bevy_")
+}
+
+/// Picks the crates whose systems are drawn as one node (recorded in `info`), and edits the graphs
+/// a schedule is laid out from to leave bevy's out if asked. A crate that is ordered both before
+/// and after another node can't be drawn as one, so it is left as it is
+pub fn group_by_crate(
+    dependencies: &mut DiGraphMap<NodeId, ()>,
+    hierarchy: &mut DiGraphMap<NodeId, ()>,
+    info: &mut ScheduleInfo,
+    grouping: &CrateGrouping,
+) {
+    let crates: HashMap<NodeId, String> = info
+        .full_names
+        .iter()
+        .filter(|(node, _)| node.is_system())
+        .map(|(&node, name)| (node, SystemName::parse(name).crate_name().to_string()))
+        .collect();
+    // The crate everything in a node is from, if there is just one
+    let node_crate = |hierarchy: &DiGraphMap<NodeId, ()>, node: NodeId| {
+        let systems = set_systems(hierarchy, node);
+        let first = crates.get(systems.first()?)?;
+        systems
+            .iter()
+            .all(|s| crates.get(s) == Some(first))
+            .then_some(first)
+    };
+
+    if grouping.hide_engine {
+        let hidden: Vec<_> = dependencies
+            .nodes()
+            .filter(|&node| node_crate(hierarchy, node).is_some_and(|c| is_engine_crate(c)))
+            .collect();
+        for node in hidden {
+            contract_node(dependencies, node);
+            hierarchy.remove_node(node);
+        }
+    }
+
+    if !grouping.enabled {
+        return;
+    }
+    let mut by_crate: Vec<(&String, Vec<NodeId>)> = Vec::new();
+    for node in dependencies.nodes().filter(NodeId::is_system) {
+        let name = &crates[&node];
+        match by_crate.iter_mut().find(|(c, _)| *c == name) {
+            Some((_, members)) => members.push(node),
+            None => by_crate.push((name, vec![node])),
+        }
+    }
+    by_crate.sort_by(|a, b| a.0.cmp(b.0));
+
+    // The systems' ordering, along with the empty sets that are drawn on their own, as merging a
+    // crate can't be allowed to go around one of those either
+    let mut quotient = DiGraphMap::new();
+    let drawn = |node: NodeId| match set_systems(hierarchy, node) {
+        systems if systems.is_empty() => vec![DrawnNode::Node(node)],
+        systems => systems.into_iter().map(DrawnNode::Node).collect(),
+    };
+    for node in dependencies.nodes() {
+        for drawn_node in drawn(node) {
+            quotient.add_node(drawn_node);
+        }
+    }
+    for (a, b, ()) in dependencies.all_edges() {
+        let b_drawn = drawn(b);
+        for a_drawn in drawn(a) {
+            for &b_drawn in &b_drawn {
+                quotient.add_edge(a_drawn, b_drawn, ());
+            }
+        }
+    }
+    for (name, members) in by_crate {
+        if members.len() < 2 || grouping.expanded.contains(name) {
+            continue;
+        }
+        let group = DrawnNode::CrateGroup(info.crate_groups.len());
+        let drawn_members: Vec<_> = members.iter().map(|&m| DrawnNode::Node(m)).collect();
+        let mut merged = quotient.clone();
+        merge_nodes(&mut merged, &drawn_members, group);
+        if !find_cycles(&merged).is_empty() {
+            continue;
+        }
+        quotient = merged;
+
+        // Sets wholly inside the crate are drawn as part of it, so move their ordering and
+        // parents onto their systems
+        let sets: Vec<_> = hierarchy
+            .nodes()
+            .filter(|&node| node.is_set() && node_crate(hierarchy, node) == Some(name))
+            .collect();
+        for set in sets {
+            let systems = set_systems(hierarchy, set);
+            let edges: Vec<_> = dependencies
+                .neighbors_directed(set, Direction::Incoming)
+                .map(|a| (a, true))
+                .chain(
+                    dependencies
+                        .neighbors_directed(set, Direction::Outgoing)
+                        .map(|b| (b, false)),
+                )
+                .collect();
+            let parents: Vec<_> = hierarchy
+                .neighbors_directed(set, Direction::Incoming)
+                .collect();
+            for &system in &systems {
+                for &(other, incoming) in &edges {
+                    if incoming {
+                        dependencies.add_edge(other, system, ());
+                    } else {
+                        dependencies.add_edge(system, other, ());
+                    }
+                }
+                for &parent in &parents {
+                    hierarchy.add_edge(parent, system, ());
+                }
+            }
+            dependencies.remove_node(set);
+            hierarchy.remove_node(set);
+        }
+
+        for &member in &members {
+            info.grouped_systems.insert(member, group);
+        }
+        info.crate_groups.push(CrateGroup {
+            name: name.clone(),
+            systems: members,
+        });
+    }
+}
+
+pub fn crate_grouping_controls(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut grouping: ResMut<CrateGrouping>,
+) {
+    if keys.just_pressed(KeyCode::KeyK) {
+        // Collapse everything again when turning it back on
+        grouping.enabled = !grouping.enabled;
+        grouping.expanded.clear();
+    }
+    if keys.just_pressed(KeyCode::KeyE) {
+        grouping.hide_engine = !grouping.hide_engine;
+    }
+}
+
+/// Clicking a crate's node shows its systems instead
+pub fn expand_crate_groups(
+    keys: Res<ButtonInput<KeyCode>>,
+    nodes: Query<(&Interaction, &GraphNode), Changed<Interaction>>,
+    info: Res<ScheduleInfo>,
+    mut grouping: ResMut<CrateGrouping>,
+) {
+    // Modified clicks are for stepping
+    if keys.any_pressed([
+        KeyCode::ControlLeft,
+        KeyCode::ControlRight,
+        KeyCode::AltLeft,
+        KeyCode::AltRight,
+    ]) {
+        return;
+    }
+    for (interaction, node) in &nodes {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        if let DrawnNode::CrateGroup(i) = node.0 {
+            grouping.expanded.insert(info.crate_groups[i].name.clone());
+        }
+    }
+}
@@ -0,0 +1,342 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    ecs::schedule::NodeId,
+    prelude::*,
+    utils::{petgraph::Direction, HashMap},
+};
+
+use crate::{
+    gantt::CapturedFrame,
+    graph_stats::SystemCosts,
+    graph_ui::NodeSelection,
+    graph_utils::{flatten_dependencies, topsort_graph},
+    schedule_graph::ScheduleInfo,
+    system_timing::SystemRun,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct SimulatedRun {
+    pub system: NodeId,
+    pub thread: usize,
+    pub start: f64,
+    pub end: f64,
+}
+
+/// What the multithreaded executor would (roughly) do with a schedule, in the same units as the
+/// costs it was given
+#[derive(Debug, Clone, Default)]
+pub struct SimulatedFrame {
+    pub threads: usize,
+    pub runs: Vec<SimulatedRun>,
+    pub frame_time: f64,
+}
+
+/// Simulates running every system in the schedule once on `threads` threads.
+///
+/// Like bevy's executor, a system starts as soon as everything it's ordered after has finished, a
+/// thread is free and nothing running conflicts with it, trying systems in topological order.
+/// Exclusive systems run alone and they (and non-send systems) run on thread 0, the main thread.
+/// Conflicts come from component access, which ignores query filters, so this can be pessimistic.
+pub fn simulate_executor(
+    info: &ScheduleInfo,
+    costs: &SystemCosts,
+    threads: usize,
+) -> SimulatedFrame {
+    let threads = threads.max(1);
+    let flattened = flatten_dependencies(&info.dependencies, &info.hierarchy);
+    let Ok(topsort) = topsort_graph(&flattened) else {
+        return SimulatedFrame::default();
+    };
+
+    // How many of the systems each one is ordered after haven't finished yet
+    let mut remaining: HashMap<NodeId, usize> = topsort
+        .iter()
+        .map(|&system| {
+            let count = flattened
+                .neighbors_directed(system, Direction::Incoming)
+                .count();
+            (system, count)
+        })
+        .collect();
+    let mut waiting = topsort;
+    let mut running: Vec<SimulatedRun> = Vec::new();
+    let mut finished: Vec<SimulatedRun> = Vec::new();
+    let mut now = 0.0;
+
+    while !waiting.is_empty() || !running.is_empty() {
+        let mut i = 0;
+        while i < waiting.len() {
+            let system = waiting[i];
+            let ready = remaining[&system] == 0;
+            let exclusive = info.exclusive_systems.contains(&system);
+            let can_run = ready
+                && (running.is_empty() || !exclusive)
+                && !running.iter().any(|r| {
+                    info.exclusive_systems.contains(&r.system)
+                        || info.conflicts.contains(&(r.system, system))
+                });
+            let thread = if exclusive || info.non_send_systems.contains(&system) {
+                (!running.iter().any(|r| r.thread == 0)).then_some(0)
+            } else {
+                // Leaving the main thread for the systems that need it while there are others
+                (1..threads)
+                    .chain([0])
+                    .find(|&t| !running.iter().any(|r| r.thread == t))
+            };
+
+            match thread {
+                Some(thread) if can_run => {
+                    let cost = info.full_names.get(&system).map_or(1.0, |n| costs.cost(n));
+                    running.push(SimulatedRun {
+                        system,
+                        thread,
+                        start: now,
+                        end: now + cost,
+                    });
+                    waiting.remove(i);
+                }
+                _ => i += 1,
+            }
+        }
+
+        // Move on to the next system finishing
+        let Some(next) = running
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.end.total_cmp(&b.end))
+            .map(|(i, _)| i)
+        else {
+            // Nothing can ever run, which shouldn't happen without cycles
+            break;
+        };
+        let run = running.swap_remove(next);
+        now = run.end;
+        for next in flattened.neighbors_directed(run.system, Direction::Outgoing) {
+            *remaining.get_mut(&next).unwrap() -= 1;
+        }
+        finished.push(run);
+    }
+
+    SimulatedFrame {
+        threads,
+        runs: finished,
+        frame_time: now,
+    }
+}
+
+#[derive(Resource, Debug)]
+pub struct SimulationSettings {
+    /// Change with + and -
+    pub threads: usize,
+    /// Whether the simulated frame is the one in the timeline window (show it with S)
+    pub shown: bool,
+}
+
+impl Default for SimulationSettings {
+    fn default() -> Self {
+        SimulationSettings {
+            threads: std::thread::available_parallelism().map_or(4, |n| n.get()),
+            shown: false,
+        }
+    }
+}
+
+pub fn simulation_controls(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut settings: ResMut<SimulationSettings>,
+) {
+    if keys.just_pressed(KeyCode::KeyS) {
+        settings.shown = true;
+    }
+    if keys.just_pressed(KeyCode::KeyG) {
+        settings.shown = false;
+    }
+    if keys.just_pressed(KeyCode::Equal) {
+        settings.threads += 1;
+    }
+    if keys.just_pressed(KeyCode::Minus) && settings.threads > 1 {
+        settings.threads -= 1;
+    }
+}
+
+/// Halves the cost of the selected systems (H), to see what speeding them up would do
+pub fn halve_selected_costs(
+    keys: Res<ButtonInput<KeyCode>>,
+    selection: Res<NodeSelection>,
+    info: Res<ScheduleInfo>,
+    mut costs: ResMut<SystemCosts>,
+) {
+    if !keys.just_pressed(KeyCode::KeyH) {
+        return;
+    }
+
+    for &node in &selection.0 {
+        for system in info.drawn_systems(node) {
+            let name = &info.full_names[&system];
+            let cost = costs.cost(name) / 2.0;
+            costs.set(name.clone(), cost);
+        }
+    }
+}
+
+pub fn show_simulation(
+    settings: Res<SimulationSettings>,
+    info: Res<ScheduleInfo>,
+    costs: Res<SystemCosts>,
+    mut captured: ResMut<CapturedFrame>,
+) {
+    if !settings.shown || !(settings.is_changed() || costs.is_changed() || info.is_changed()) {
+        return;
+    }
+
+    let simulated = simulate_executor(&info, &costs, settings.threads);
+    // Costs are treated as milliseconds so the timeline can draw them like a real frame
+    let start = Instant::now();
+    let at = |time: f64| start + Duration::from_secs_f64(time / 1000.0);
+    let label: Arc<str> = info.label.as_str().into();
+    let thread_names: Vec<Arc<str>> = (0..simulated.threads)
+        .map(|t| format!("Simulated thread {t}").into())
+        .collect();
+
+    captured.title = format!(
+        "Simulated {} on {} threads, estimated {:.2}",
+        info.label, simulated.threads, simulated.frame_time
+    );
+    captured.runs = simulated
+        .runs
+        .iter()
+        .map(|run| SystemRun {
+            schedule: Some(label.clone()),
+            name: info.full_names[&run.system].as_str().into(),
+            thread: thread_names[run.thread].clone(),
+            start: at(run.start),
+            end: at(run.end),
+        })
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::any::type_name_of_val;
+
+    use super::*;
+
+    #[derive(Resource, Default)]
+    struct Counter(usize);
+
+    #[derive(Default)]
+    struct MainThreadOnly;
+
+    fn first() {}
+    fn second() {}
+    fn third() {}
+    fn write_a(mut counter: ResMut<Counter>) {
+        counter.0 += 1;
+    }
+    fn write_b(mut counter: ResMut<Counter>) {
+        counter.0 += 1;
+    }
+    fn exclusive(_world: &mut World) {}
+    fn non_send_a(_main_thread: NonSend<MainThreadOnly>) {}
+    fn non_send_b(_main_thread: NonSend<MainThreadOnly>) {}
+
+    /// Each system's (thread, start, end), by its short name, and the frame time
+    fn simulate(
+        app: &mut App,
+        costs: &SystemCosts,
+        threads: usize,
+    ) -> (HashMap<String, (usize, f64, f64)>, f64) {
+        app.init_resource::<Counter>()
+            .init_non_send_resource::<MainThreadOnly>();
+        app.update();
+        let info = ScheduleInfo::new(app.world.resource::<Schedules>().get(Update).unwrap());
+        let frame = simulate_executor(&info, costs, threads);
+        assert_eq!(frame.threads, threads.max(1));
+
+        let runs = frame
+            .runs
+            .iter()
+            .map(|run| {
+                let name = info.full_names[&run.system].rsplit("::").next().unwrap();
+                (name.to_string(), (run.thread, run.start, run.end))
+            })
+            .collect();
+        (runs, frame.frame_time)
+    }
+
+    fn overlap(a: (usize, f64, f64), b: (usize, f64, f64)) -> bool {
+        a.1 < b.2 && b.1 < a.2
+    }
+
+    #[test]
+    fn dependencies() {
+        let mut app = App::new();
+        app.add_systems(Update, ((first, second).chain(), third.after(first)));
+        let mut costs = SystemCosts::default();
+        costs.set(type_name_of_val(&first), 2.0);
+        let (runs, frame_time) = simulate(&mut app, &costs, 4);
+
+        assert_eq!(frame_time, 3.0);
+        assert_eq!((runs["first"].1, runs["first"].2), (0.0, 2.0));
+        // Both start as soon as first is done, on different threads
+        assert_eq!((runs["second"].1, runs["second"].2), (2.0, 3.0));
+        assert_eq!((runs["third"].1, runs["third"].2), (2.0, 3.0));
+        assert_ne!(runs["second"].0, runs["third"].0);
+    }
+
+    #[test]
+    fn conflicting_access() {
+        let mut app = App::new();
+        app.add_systems(Update, (write_a, write_b, first));
+        let (runs, frame_time) = simulate(&mut app, &SystemCosts::default(), 4);
+
+        assert_eq!(frame_time, 2.0);
+        assert!(!overlap(runs["write_a"], runs["write_b"]));
+        assert_eq!(runs["first"].1, 0.0);
+    }
+
+    #[test]
+    fn exclusive_systems() {
+        let mut app = App::new();
+        app.add_systems(Update, (first, exclusive, second));
+        let (runs, frame_time) = simulate(&mut app, &SystemCosts::default(), 4);
+
+        // Runs on the main thread, with nothing alongside it
+        assert_eq!(frame_time, 2.0);
+        assert_eq!(runs["exclusive"].0, 0);
+        assert!(!overlap(runs["exclusive"], runs["first"]));
+        assert!(!overlap(runs["exclusive"], runs["second"]));
+        assert!(overlap(runs["first"], runs["second"]));
+    }
+
+    #[test]
+    fn non_send_systems() {
+        let mut app = App::new();
+        app.add_systems(Update, (non_send_a, non_send_b, first));
+        let (runs, frame_time) = simulate(&mut app, &SystemCosts::default(), 4);
+
+        // Both have to take turns on the main thread, while the other system goes elsewhere
+        assert_eq!(frame_time, 2.0);
+        assert_eq!(runs["non_send_a"].0, 0);
+        assert_eq!(runs["non_send_b"].0, 0);
+        assert_ne!(runs["first"].0, 0);
+        assert_eq!(runs["first"].1, 0.0);
+    }
+
+    #[test]
+    fn thread_count() {
+        let mut app = App::new();
+        app.add_systems(Update, (first, second, third));
+        let frame_time = |app: &mut App, threads| simulate(app, &SystemCosts::default(), threads).1;
+
+        assert_eq!(frame_time(&mut app, 3), 1.0);
+        assert_eq!(frame_time(&mut app, 2), 2.0);
+        assert_eq!(frame_time(&mut app, 1), 3.0);
+        // Never fewer than one thread
+        assert_eq!(frame_time(&mut app, 0), 3.0);
+    }
+}
@@ -0,0 +1,189 @@
+use bevy::{
+    ecs::{component::Tick, schedule::NodeId},
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+use crate::{
+    graph_ui::GraphNodeLabel,
+    graph_utils::set_systems,
+    schedule_graph::{schedule_systems, ScheduleInfo},
+    shorten_type::SystemName,
+    system_timing::{timing_layer_installed, RecentSystemRuns},
+};
+
+/// What a run condition returned, as far as can be worked out from what ran
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConditionResult {
+    True,
+    False,
+    /// It ran but something else it's combined with could have been the false one
+    Unknown,
+    NotEvaluated,
+}
+
+/// Which systems of the displayed schedule ran last frame
+#[derive(Resource, Debug, Default)]
+pub struct FrameRuns {
+    /// How many times the schedule ran, only counted with the timing layer, otherwise 0 or 1
+    pub schedule_runs: u32,
+    /// How many times each system ran last frame
+    pub runs: HashMap<NodeId, u32>,
+    /// How many times each system has run since the app started
+    pub totals: HashMap<NodeId, u64>,
+    pub conditions: HashMap<NodeId, Vec<(String, ConditionResult)>>,
+    last_ticks: HashMap<NodeId, Tick>,
+}
+
+impl FrameRuns {
+    pub fn ran(&self, node: NodeId) -> u32 {
+        self.runs.get(&node).copied().unwrap_or(0)
+    }
+}
+
+pub fn track_frame_runs(
+    schedules: Res<Schedules>,
+    info: Res<ScheduleInfo>,
+    recent: Res<RecentSystemRuns>,
+    mut frame_runs: ResMut<FrameRuns>,
+) {
+    // Sub-apps' schedules aren't in this world, and bevy sets the ticks when it first builds one
+    let Some(schedule) = schedules
+        .get(info.schedule)
+        .filter(|s| info.app.is_none() && s.systems().is_ok())
+    else {
+        return;
+    };
+    let frame_runs = frame_runs.as_mut();
+    let traced = timing_layer_installed();
+
+    // The ticks say whether a system ran at all, the timing layer (if there is one) says how often
+    let mut traced_runs: HashMap<&str, u32> = HashMap::new();
+    for run in &recent.last_frame {
+        if run.schedule.as_deref() == Some(&info.label) {
+            *traced_runs.entry(&run.name).or_default() += 1;
+        }
+    }
+
+    frame_runs.runs.clear();
+    for (node, system) in schedule_systems(schedule) {
+        let tick = system.get_last_run();
+        // The first tick seen could be from any frame
+        match frame_runs.last_ticks.insert(node, tick) {
+            Some(last) if last != tick => {}
+            _ => continue,
+        }
+        let runs = match traced_runs.get(&*system.name()) {
+            Some(&runs) if traced => runs,
+            _ => 1,
+        };
+        frame_runs.runs.insert(node, runs);
+        *frame_runs.totals.entry(node).or_default() += runs as u64;
+    }
+
+    frame_runs.schedule_runs = if traced {
+        recent
+            .last_frame_schedules
+            .get(info.label.as_str())
+            .copied()
+            .unwrap_or(0)
+    } else {
+        u32::from(!frame_runs.runs.is_empty())
+    };
+
+    // Conditions show up in the timing layer as systems of their own
+    let evaluated: HashSet<&str> = traced_runs.keys().copied().collect();
+    frame_runs.conditions = info
+        .conditions
+        .iter()
+        .map(|(&node, conditions)| {
+            let ran = set_systems(&info.hierarchy, node)
+                .iter()
+                .any(|&system| frame_runs.ran(system) > 0);
+            let results = conditions
+                .iter()
+                .map(|condition| {
+                    let result = if ran {
+                        ConditionResult::True
+                    } else if !traced {
+                        ConditionResult::Unknown
+                    } else if !evaluated.contains(condition.as_str()) {
+                        ConditionResult::NotEvaluated
+                    } else if conditions.len() == 1 {
+                        ConditionResult::False
+                    } else {
+                        ConditionResult::Unknown
+                    };
+                    (condition.clone(), result)
+                })
+                .collect();
+            (node, results)
+        })
+        .collect();
+}
+
+#[derive(Component)]
+pub struct FrameRunsText;
+
+pub fn show_frame_runs(
+    frame_runs: Res<FrameRuns>,
+    info: Res<ScheduleInfo>,
+    mut labels: Query<(&GraphNodeLabel, &mut Text), Without<FrameRunsText>>,
+    mut text: Query<&mut Text, With<FrameRunsText>>,
+) {
+    if !frame_runs.is_changed() {
+        return;
+    }
+
+    for (label, mut text) in &mut labels {
+        // Sets are drawn as one node, so they count as running if any of their systems did
+        let systems = info.drawn_systems(label.0);
+        let runs = systems
+            .iter()
+            .map(|&s| frame_runs.ran(s))
+            .max()
+            .unwrap_or(0);
+        let total = systems
+            .iter()
+            .filter_map(|s| frame_runs.totals.get(s))
+            .max()
+            .copied()
+            .unwrap_or(0);
+        text.sections[2].value = match runs {
+            0 if frame_runs.schedule_runs > 0 => format!(" skipped [{total}]"),
+            0 | 1 => format!(" [{total}]"),
+            runs => format!(" {runs}x [{total}]"),
+        };
+    }
+
+    let mut description = format!(
+        "{} ran {} time{} last frame",
+        info.label,
+        frame_runs.schedule_runs,
+        if frame_runs.schedule_runs == 1 {
+            ""
+        } else {
+            "s"
+        }
+    );
+    let mut conditions: Vec<_> = frame_runs.conditions.iter().collect();
+    conditions.sort_by_key(|(&node, _)| info.name(node));
+    for (&node, results) in conditions {
+        for (condition, result) in results {
+            let result = match result {
+                ConditionResult::True => "true",
+                ConditionResult::False => "false",
+                ConditionResult::Unknown => "?",
+                ConditionResult::NotEvaluated => "not evaluated",
+            };
+            description.push_str(&format!(
+                "\n{}.run_if({}): {result}",
+                info.name(node),
+                SystemName::parse(condition).shortest()
+            ));
+        }
+    }
+    for mut text in &mut text {
+        text.sections[0].value = description.clone();
+    }
+}
@@ -0,0 +1,240 @@
+use std::{sync::Arc, time::Instant};
+
+use bevy::{
+    prelude::*,
+    render::camera::RenderTarget,
+    ui::TargetCamera,
+    utils::{HashMap, HashSet},
+    window::WindowRef,
+};
+
+use crate::{
+    graph_ui::NodeSelection,
+    layer_graph::LayerGraph,
+    schedule_graph::ScheduleInfo,
+    shorten_type::SystemName,
+    system_timing::{RecentSystemRuns, SystemRun},
+};
+
+const LABEL_WIDTH: f32 = 220.0;
+const TIMELINE_WIDTH: f32 = 1000.0;
+const ROW_HEIGHT: f32 = 26.0;
+const HEADER_HEIGHT: f32 = 30.0;
+
+/// A frame's worth of system runs, captured with G
+#[derive(Resource, Default)]
+pub struct CapturedFrame {
+    pub title: String,
+    pub runs: Vec<SystemRun>,
+}
+
+#[derive(Component)]
+struct GanttWindow;
+
+#[derive(Component)]
+pub struct GanttCamera;
+
+#[derive(Component)]
+pub struct GanttRoot;
+
+#[derive(Component)]
+pub struct GanttBar {
+    pub schedule: Option<Arc<str>>,
+    pub name: Arc<str>,
+}
+
+pub fn capture_frame(
+    keys: Res<ButtonInput<KeyCode>>,
+    recent: Res<RecentSystemRuns>,
+    mut captured: ResMut<CapturedFrame>,
+) {
+    if keys.just_pressed(KeyCode::KeyG) {
+        captured.title = "Captured frame".to_string();
+        captured.runs = recent.last_frame.clone();
+    }
+}
+
+// A stable colour for each schedule so they can be told apart
+fn schedule_colour(schedule: Option<&str>) -> Color {
+    let hash = schedule
+        .unwrap_or_default()
+        .bytes()
+        .fold(7u32, |h, b| h.wrapping_mul(31).wrapping_add(b as u32));
+    Color::hsl((hash % 360) as f32, 0.5, 0.45)
+}
+
+pub fn draw_gantt(
+    mut commands: Commands,
+    captured: Res<CapturedFrame>,
+    cameras: Query<Entity, With<GanttCamera>>,
+    roots: Query<Entity, With<GanttRoot>>,
+) {
+    if !captured.is_changed() || captured.runs.is_empty() {
+        return;
+    }
+
+    let camera = match cameras.get_single() {
+        Ok(camera) => camera,
+        Err(_) => {
+            let window = commands
+                .spawn((
+                    Window {
+                        title: "Frame Timeline".to_string(),
+                        ..default()
+                    },
+                    GanttWindow,
+                ))
+                .id();
+            commands
+                .spawn((
+                    Camera2dBundle {
+                        camera: Camera {
+                            target: RenderTarget::Window(WindowRef::Entity(window)),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    GanttCamera,
+                ))
+                .id()
+        }
+    };
+    for root in &roots {
+        commands.entity(root).despawn_recursive();
+    }
+
+    let runs = &captured.runs;
+    let start = runs.iter().map(|r| r.start).min().unwrap();
+    let end = runs.iter().map(|r| r.end).max().unwrap();
+    let frame_ms = (end - start).as_secs_f32() * 1000.0;
+    let px_per_ms = TIMELINE_WIDTH / frame_ms.max(0.001);
+    let x = |instant: Instant| LABEL_WIDTH + (instant - start).as_secs_f32() * 1000.0 * px_per_ms;
+
+    // The main thread first, then the rest by name
+    let mut threads: Vec<_> = runs.iter().map(|r| r.thread.clone()).collect();
+    threads.sort_by_key(|t| (!t.contains("main"), t.len(), t.clone()));
+    threads.dedup();
+    let mut busy: HashMap<Arc<str>, f32> = HashMap::new();
+    for run in runs {
+        *busy.entry(run.thread.clone()).or_default() += run.duration().as_secs_f32() * 1000.0;
+    }
+
+    let text_style = |font_size| TextStyle {
+        font_size,
+        color: Color::WHITE,
+        ..default()
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    ..default()
+                },
+                ..default()
+            },
+            GanttRoot,
+            TargetCamera(camera),
+        ))
+        .with_children(|builder| {
+            let schedules: HashSet<_> = runs.iter().filter_map(|r| r.schedule.clone()).collect();
+            builder.spawn(
+                TextBundle::from_section(
+                    format!(
+                        "{}: {frame_ms:.2}ms, {} systems, {} schedules on {} threads \
+                        (G to capture, S to simulate, click a bar to select it in the graph)",
+                        captured.title,
+                        runs.len(),
+                        schedules.len(),
+                        threads.len()
+                    ),
+                    text_style(18.0),
+                )
+                .with_style(Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(10.0),
+                    top: Val::Px(5.0),
+                    ..default()
+                })
+                .with_no_wrap(),
+            );
+
+            for (row, thread) in threads.iter().enumerate() {
+                let top = HEADER_HEIGHT + row as f32 * ROW_HEIGHT;
+                let busy_percent = busy[thread] / frame_ms.max(0.001) * 100.0;
+                builder.spawn(
+                    TextBundle::from_section(
+                        format!("{thread} ({busy_percent:.0}% busy)"),
+                        text_style(14.0),
+                    )
+                    .with_style(Style {
+                        position_type: PositionType::Absolute,
+                        left: Val::Px(5.0),
+                        top: Val::Px(top + 4.0),
+                        width: Val::Px(LABEL_WIDTH - 10.0),
+                        overflow: Overflow::clip(),
+                        ..default()
+                    })
+                    .with_no_wrap(),
+                );
+
+                for run in runs.iter().filter(|r| r.thread == *thread) {
+                    let left = x(run.start);
+                    let width = (x(run.end) - left).max(1.0);
+                    builder
+                        .spawn((
+                            NodeBundle {
+                                style: Style {
+                                    position_type: PositionType::Absolute,
+                                    left: Val::Px(left),
+                                    top: Val::Px(top),
+                                    width: Val::Px(width),
+                                    height: Val::Px(ROW_HEIGHT - 4.0),
+                                    overflow: Overflow::clip(),
+                                    ..default()
+                                },
+                                background_color: schedule_colour(run.schedule.as_deref()).into(),
+                                ..default()
+                            },
+                            Interaction::default(),
+                            GanttBar {
+                                schedule: run.schedule.clone(),
+                                name: run.name.clone(),
+                            },
+                        ))
+                        .with_children(|builder| {
+                            // From any schedule, not just the graphed one
+                            let short_name = SystemName::parse(&run.name).shortest();
+                            builder.spawn(
+                                TextBundle::from_section(short_name, text_style(12.0))
+                                    .with_no_wrap(),
+                            );
+                        });
+                }
+            }
+        });
+}
+
+pub fn select_gantt_bar(
+    bars: Query<(&Interaction, &GanttBar), Changed<Interaction>>,
+    layer_graph: Res<LayerGraph>,
+    info: Res<ScheduleInfo>,
+    mut selection: ResMut<NodeSelection>,
+) {
+    for (interaction, bar) in &bars {
+        if *interaction != Interaction::Pressed || bar.schedule.as_deref() != Some(&info.label) {
+            continue;
+        }
+
+        let systems = info
+            .full_names
+            .iter()
+            .filter(|(node, name)| node.is_system() && name.as_str() == &*bar.name);
+        for (&system, _) in systems {
+            for node in layer_graph.displayed_as(&info, system) {
+                selection.select(node);
+            }
+        }
+    }
+}
@@ -0,0 +1,373 @@
+use std::any::TypeId;
+
+use bevy::{
+    ecs::schedule::{NodeId, ScheduleLabel},
+    prelude::*,
+    utils::{
+        petgraph::{graphmap::DiGraphMap, Direction},
+        HashSet,
+    },
+};
+
+use crate::{
+    graph_utils::{check_graph, flatten_dependencies, shortest_path, topsort_graph},
+    schedule_graph::{prebuild_schedule, schedule_systems, ScheduleInfo},
+};
+
+/// Something that picks out a single system, either the system function itself or its name
+pub trait SystemSelector<Marker> {
+    fn into_matcher(self) -> SystemMatcher;
+}
+
+pub enum SystemMatcher {
+    Type(TypeId, String),
+    Name(String),
+}
+
+impl SystemSelector<()> for &str {
+    fn into_matcher(self) -> SystemMatcher {
+        SystemMatcher::Name(self.to_string())
+    }
+}
+
+pub struct SystemTypeMarker;
+
+impl<M, S: IntoSystemSet<M>> SystemSelector<(SystemTypeMarker, M)> for S {
+    fn into_matcher(self) -> SystemMatcher {
+        let set = self.into_system_set();
+        match set.system_type() {
+            Some(type_id) => SystemMatcher::Type(type_id, format!("{:?}", set)),
+            // Not a system function, so the best we can do is its name
+            None => SystemMatcher::Name(format!("{:?}", set)),
+        }
+    }
+}
+
+impl SystemMatcher {
+    fn description(&self) -> &str {
+        match self {
+            SystemMatcher::Type(_, name) | SystemMatcher::Name(name) => name,
+        }
+    }
+}
+
+// Everything the asserts need to know about one schedule
+pub(crate) struct ScheduleOrdering {
+    pub(crate) info: ScheduleInfo,
+    // (node, type)
+    systems: Vec<(NodeId, TypeId)>,
+    flattened: DiGraphMap<NodeId, ()>,
+    connected: HashSet<(NodeId, NodeId)>,
+    conflicts: HashSet<(NodeId, NodeId)>,
+    sets: Vec<(NodeId, Box<dyn SystemSet>)>,
+}
+
+impl ScheduleOrdering {
+    pub(crate) fn new(app: &mut App, label: impl ScheduleLabel) -> Self {
+        let label = label.intern();
+        app.world
+            .resource_scope::<Schedules, _>(|world, mut schedules| {
+                let ignored_ambiguities = schedules.ignored_scheduling_ambiguities.clone();
+                let schedule = schedules
+                    .get_mut(label)
+                    .unwrap_or_else(|| panic!("No schedule called {:?}", label));
+                if let Err(e) = prebuild_schedule(schedule, world, &ignored_ambiguities) {
+                    panic!("Schedule {:?} failed to build: {e}", label);
+                }
+
+                let graph = schedule.graph();
+                let info = ScheduleInfo::new(schedule);
+                let flattened = flatten_dependencies(&info.dependencies, &info.hierarchy);
+                let topsort = topsort_graph(&flattened).unwrap();
+
+                ScheduleOrdering {
+                    systems: schedule_systems(schedule)
+                        .iter()
+                        .map(|(n, s)| (*n, System::type_id(*s)))
+                        .collect(),
+                    connected: check_graph(&flattened, &topsort).connected,
+                    conflicts: graph
+                        .conflicting_systems()
+                        .iter()
+                        .flat_map(|&(a, b, _)| [(a, b), (b, a)])
+                        .collect(),
+                    sets: graph
+                        .system_sets()
+                        .map(|(n, s, _)| (n, s.dyn_clone()))
+                        .collect(),
+                    info,
+                    flattened,
+                }
+            })
+    }
+
+    pub(crate) fn resolve(&self, matcher: &SystemMatcher) -> NodeId {
+        let matches: Vec<_> = self
+            .systems
+            .iter()
+            .filter(|(node, type_id)| match matcher {
+                SystemMatcher::Type(ty, _) => ty == type_id,
+                SystemMatcher::Name(name) => {
+                    let full_name = &self.info.full_names[node];
+                    full_name == name
+                        || self.info.name(*node) == name
+                        || full_name.ends_with(&format!("::{name}"))
+                }
+            })
+            .collect();
+
+        match matches.as_slice() {
+            [(node, _)] => *node,
+            [] => panic!(
+                "No system matching `{}` in {}",
+                matcher.description(),
+                self.info.label
+            ),
+            _ => panic!(
+                "`{}` is ambiguous in {}, it matches {}",
+                matcher.description(),
+                self.info.label,
+                matches
+                    .iter()
+                    .map(|(node, _)| format!("`{}`", self.info.full_names[node]))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+
+    fn runs_before(&self, a: NodeId, b: NodeId) -> bool {
+        self.connected.contains(&(a, b))
+    }
+
+    fn neighbour_names(&self, node: NodeId, direction: Direction) -> String {
+        self.flattened
+            .neighbors_directed(node, direction)
+            .map(|n| self.info.name(n))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn path(&self, a: NodeId, b: NodeId) -> Option<String> {
+        shortest_path(&self.flattened, &[a], &[b]).map(|path| {
+            path.iter()
+                .map(|n| self.info.name(*n))
+                .collect::<Vec<_>>()
+                .join(" -> ")
+        })
+    }
+}
+
+/// Panics unless `a` is ordered (possibly transitively) before `b` in `schedule`
+pub fn assert_runs_before<M1, M2>(
+    app: &mut App,
+    schedule: impl ScheduleLabel,
+    a: impl SystemSelector<M1>,
+    b: impl SystemSelector<M2>,
+) {
+    let ordering = ScheduleOrdering::new(app, schedule);
+    let (a, b) = (
+        ordering.resolve(&a.into_matcher()),
+        ordering.resolve(&b.into_matcher()),
+    );
+    if ordering.runs_before(a, b) {
+        return;
+    }
+
+    let (a_name, b_name) = (&ordering.info.names[&a], &ordering.info.names[&b]);
+    match ordering.path(b, a) {
+        Some(path) => panic!(
+            "Expected `{a_name}` to run before `{b_name}` in {}, but it runs after it: {path}",
+            ordering.info.label
+        ),
+        None => panic!(
+            "Expected `{a_name}` to run before `{b_name}` in {}, but nothing orders them. \
+            Missing link: `{a_name}` only runs before [{}] and `{b_name}` only runs after [{}]",
+            ordering.info.label,
+            ordering.neighbour_names(a, Direction::Outgoing),
+            ordering.neighbour_names(b, Direction::Incoming),
+        ),
+    }
+}
+
+/// Panics if there is any ordering (in either direction) between `a` and `b` in `schedule`
+pub fn assert_unordered<M1, M2>(
+    app: &mut App,
+    schedule: impl ScheduleLabel,
+    a: impl SystemSelector<M1>,
+    b: impl SystemSelector<M2>,
+) {
+    let ordering = ScheduleOrdering::new(app, schedule);
+    let (a, b) = (
+        ordering.resolve(&a.into_matcher()),
+        ordering.resolve(&b.into_matcher()),
+    );
+
+    if let Some(path) = ordering.path(a, b).or_else(|| ordering.path(b, a)) {
+        panic!(
+            "Expected `{}` and `{}` to be unordered in {}, but found: {path}",
+            ordering.info.names[&a], ordering.info.names[&b], ordering.info.label
+        );
+    }
+}
+
+/// Panics unless `system` is (possibly through other sets) in `set` in `schedule`
+pub fn assert_in_set<M>(
+    app: &mut App,
+    schedule: impl ScheduleLabel,
+    system: impl SystemSelector<M>,
+    set: impl SystemSet,
+) {
+    let ordering = ScheduleOrdering::new(app, schedule);
+    let system = ordering.resolve(&system.into_matcher());
+    let system_name = &ordering.info.names[&system];
+
+    let Some((set_node, _)) = ordering.sets.iter().find(|(_, s)| **s == *set.dyn_clone()) else {
+        panic!(
+            "Expected `{system_name}` to be in {:?}, but that set isn't in {}",
+            set, ordering.info.label
+        );
+    };
+
+    if shortest_path(&ordering.info.hierarchy, &[*set_node], &[system]).is_none() {
+        let parents: Vec<_> = ordering
+            .info
+            .hierarchy
+            .neighbors_directed(system, Direction::Incoming)
+            .filter_map(|parent| {
+                let (_, parent_set) = ordering.sets.iter().find(|(n, _)| *n == parent)?;
+                // Every system is in its own type set, that isn't interesting
+                parent_set
+                    .system_type()
+                    .is_none()
+                    .then(|| format!("{:?}", parent_set))
+            })
+            .collect();
+        panic!(
+            "Expected `{system_name}` to be in {:?} in {}, but it is only directly in [{}]",
+            set,
+            ordering.info.label,
+            parents.join(", ")
+        );
+    }
+}
+
+/// Panics if `a` and `b` conflict without being ordered, so that bevy would report an ambiguity
+pub fn assert_not_ambiguous<M1, M2>(
+    app: &mut App,
+    schedule: impl ScheduleLabel,
+    a: impl SystemSelector<M1>,
+    b: impl SystemSelector<M2>,
+) {
+    let ordering = ScheduleOrdering::new(app, schedule);
+    let (a, b) = (
+        ordering.resolve(&a.into_matcher()),
+        ordering.resolve(&b.into_matcher()),
+    );
+
+    if ordering.conflicts.contains(&(a, b)) {
+        panic!(
+            "Expected `{}` and `{}` not to be ambiguous in {}, but they have conflicting access \
+            and no ordering, missing link: add `.before`/`.after` between them or `.ambiguous_with`",
+            ordering.info.names[&a], ordering.info.names[&b], ordering.info.label
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Resource, Default)]
+    struct Counter(u32);
+
+    #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+    struct Inner;
+
+    #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+    struct Outer;
+
+    fn first(mut counter: ResMut<Counter>) {
+        counter.0 += 1;
+    }
+
+    fn second(mut counter: ResMut<Counter>) {
+        counter.0 *= 2;
+    }
+
+    fn third(mut counter: ResMut<Counter>) {
+        counter.0 = 0;
+    }
+
+    fn reader(_counter: Res<Counter>) {}
+
+    // first -> second (through Inner, which is in Outer), third unordered and conflicting
+    fn app() -> App {
+        let mut app = App::new();
+        app.init_resource::<Counter>()
+            .configure_sets(Update, Inner.in_set(Outer))
+            .add_systems(Update, (first.before(Inner), second.in_set(Inner), third));
+        app
+    }
+
+    #[test]
+    fn runs_before() {
+        assert_runs_before(&mut app(), Update, first, second);
+        assert_runs_before(&mut app(), Update, "first", "second");
+    }
+
+    #[test]
+    #[should_panic(expected = "but it runs after it")]
+    fn runs_before_reversed() {
+        assert_runs_before(&mut app(), Update, second, first);
+    }
+
+    #[test]
+    #[should_panic(expected = "but nothing orders them")]
+    fn runs_before_unordered() {
+        assert_runs_before(&mut app(), Update, first, third);
+    }
+
+    #[test]
+    fn unordered() {
+        assert_unordered(&mut app(), Update, first, third);
+    }
+
+    #[test]
+    #[should_panic(expected = "to be unordered")]
+    fn unordered_ordered() {
+        assert_unordered(&mut app(), Update, second, first);
+    }
+
+    #[test]
+    fn in_set() {
+        assert_in_set(&mut app(), Update, second, Inner);
+        assert_in_set(&mut app(), Update, second, Outer);
+    }
+
+    #[test]
+    #[should_panic(expected = "it is only directly in []")]
+    fn in_set_outside() {
+        assert_in_set(&mut app(), Update, first, Outer);
+    }
+
+    #[test]
+    fn not_ambiguous() {
+        let mut app = app();
+        app.add_systems(Update, reader.after(third));
+        assert_not_ambiguous(&mut app, Update, first, second);
+        assert_not_ambiguous(&mut app, Update, third, reader);
+    }
+
+    #[test]
+    #[should_panic(expected = "not to be ambiguous")]
+    fn not_ambiguous_conflicting() {
+        assert_not_ambiguous(&mut app(), Update, first, third);
+    }
+
+    #[test]
+    #[should_panic(expected = "No system matching `missing`")]
+    fn missing_system() {
+        assert_runs_before(&mut app(), Update, first, "missing");
+    }
+}
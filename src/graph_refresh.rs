@@ -0,0 +1,167 @@
+use bevy::{app::InternedAppLabel, ecs::schedule::InternedScheduleLabel, prelude::*};
+
+use crate::{
+    frame_runs::FrameRuns,
+    graph_stepping::switch_stepping_schedule,
+    graph_ui::NodeSelection,
+    schedule_graph::{build_schedule_graph, schedule_graph_layout, LayoutSettings, ScheduleInfo},
+    sub_apps::SubAppGraphs,
+};
+
+/// Send this to lay out and redraw the graph, even if the schedule looks the same
+#[derive(Event, Debug, Default, Clone, Copy)]
+pub struct RefreshScheduleGraph;
+
+/// Enough about a schedule to notice when systems or sets are added to it (or it is rebuilt)
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ScheduleSignature {
+    pub exists: bool,
+    pub systems: usize,
+    pub sets: usize,
+    pub dependencies: usize,
+    pub hierarchy: usize,
+}
+
+impl ScheduleSignature {
+    pub fn new(schedule: Option<&Schedule>) -> Self {
+        let Some(schedule) = schedule else {
+            return ScheduleSignature::default();
+        };
+        let graph = schedule.graph();
+        ScheduleSignature {
+            exists: true,
+            systems: schedule.systems_len(),
+            sets: graph.system_sets().count(),
+            dependencies: graph.dependency().graph().edge_count(),
+            hierarchy: graph.hierarchy().graph().edge_count(),
+        }
+    }
+}
+
+/// Graph a different schedule
+#[derive(Event, Debug, Clone, Copy)]
+pub struct ShowSchedule {
+    /// The sub-app it is in, `None` for the main app
+    pub app: Option<InternedAppLabel>,
+    pub schedule: InternedScheduleLabel,
+}
+
+pub fn refresh_controls(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut refresh: EventWriter<RefreshScheduleGraph>,
+) {
+    if keys.just_pressed(KeyCode::KeyR) {
+        refresh.send(RefreshScheduleGraph);
+    }
+}
+
+/// Lays the graph out again when the schedule has changed or a `RefreshScheduleGraph` is sent (R),
+/// or lays out another schedule when a `ShowSchedule` is sent
+pub fn refresh_schedule_graph(world: &mut World) {
+    let settings_changed = LayoutSettings::changed(world);
+    let mut requested = world
+        .resource_mut::<Events<RefreshScheduleGraph>>()
+        .drain()
+        .count()
+        > 0
+        || settings_changed;
+    let (old_app, old_label) = {
+        let info = world.resource::<ScheduleInfo>();
+        (info.app, info.schedule)
+    };
+    let switch_to = world
+        .resource_mut::<Events<ShowSchedule>>()
+        .drain()
+        .last()
+        .filter(|show| (show.app, show.schedule) != (old_app, old_label));
+    let (app, label) = switch_to.map_or((old_app, old_label), |show| (show.app, show.schedule));
+
+    // Sub-apps' schedules can only be seen by their own systems, which snapshot them
+    let snapshot = app.and_then(|app| world.resource::<SubAppGraphs>().get(app).cloned());
+    let mut snapshot = snapshot.as_ref().map(|s| s.lock().unwrap());
+    if let Some(snapshot) = &mut snapshot {
+        snapshot.requested = Some(label);
+        if settings_changed || switch_to.is_some() {
+            snapshot.settings = LayoutSettings::from_world(world);
+        }
+        // It's laid out again with the new settings when the sub-app next runs
+        requested |= world.resource::<ScheduleInfo>().settings != snapshot.settings;
+    }
+    let (built, signature) = match &snapshot {
+        Some(snapshot) => match &snapshot.graph {
+            Some((signature, _, info))
+                if info.schedule == label && info.settings == snapshot.settings =>
+            {
+                (true, *signature)
+            }
+            _ => (false, ScheduleSignature::default()),
+        },
+        None => {
+            let schedule = world.resource::<Schedules>().get(label);
+            (
+                schedule.is_none_or(|s| s.systems().is_ok()),
+                ScheduleSignature::new(schedule),
+            )
+        }
+    };
+    if switch_to.is_none() {
+        // Wait for bevy to build it rather than building it early
+        if !built || (!requested && signature == *world.resource::<ScheduleSignature>()) {
+            return;
+        }
+    }
+
+    let laid_out = match snapshot {
+        Some(snapshot) => match &snapshot.graph {
+            Some((_, layer_graph, info)) if built => Ok((layer_graph.clone(), info.clone())),
+            _ => schedule_graph_layout(&Schedule::new(label), &snapshot.settings).map(
+                |(layer_graph, mut info)| {
+                    info.conditions = snapshot.conditions.get(&label).cloned().unwrap_or_default();
+                    (layer_graph, info)
+                },
+            ),
+        },
+        None => build_schedule_graph(In(label), world),
+    };
+    let (layer_graph, mut info) = match laid_out {
+        Ok(laid_out) => laid_out,
+        Err(e) => {
+            error!("Couldn't lay out {:?}: {e}", label);
+            // Keep showing what was there before, and don't try again until something changes
+            world.insert_resource(signature);
+            return;
+        }
+    };
+    info.app = app;
+    if switch_to.is_none() {
+        // Bevy moves the run conditions out of the graph when it builds the schedule, so keep the
+        // ones seen before
+        let old_info = world.resource::<ScheduleInfo>();
+        for (node, conditions) in &old_info.conditions {
+            if info.full_names.contains_key(node) {
+                info.conditions
+                    .entry(*node)
+                    .or_insert_with(|| conditions.clone());
+            }
+        }
+        // Nodes can disappear when it's grouped differently
+        world
+            .resource_mut::<NodeSelection>()
+            .0
+            .retain(|node| layer_graph.contains(node));
+    } else {
+        // Everything keyed by node is about the old schedule
+        world.resource_mut::<NodeSelection>().0.clear();
+        world.insert_resource(FrameRuns::default());
+        switch_stepping_schedule(world, old_label, label);
+    }
+
+    // Lay it out again once bevy has built it
+    world.insert_resource(if built {
+        signature
+    } else {
+        ScheduleSignature::default()
+    });
+    world.insert_resource(layer_graph);
+    world.insert_resource(info);
+}
@@ -0,0 +1,199 @@
+use std::fmt;
+
+use bevy::{ecs::schedule::NodeId, prelude::*, utils::HashMap};
+
+use crate::{
+    graph_utils::{
+        check_graph, flatten_dependencies, longest_path, max_antichain_width, topsort_graph,
+    },
+    layer_graph::LayerGraph,
+    schedule_graph::ScheduleInfo,
+    system_timing::SystemTimings,
+};
+
+/// Cost estimates for systems, keyed by their full name.
+///
+/// Costs set by hand win over measured ones, and systems with neither cost 1.
+#[derive(Resource, Debug, Default, Clone)]
+pub struct SystemCosts {
+    pub manual: HashMap<String, f64>,
+    /// Average milliseconds per frame from the timing overlay
+    pub measured: HashMap<String, f64>,
+}
+
+impl SystemCosts {
+    pub fn set(&mut self, system_name: impl Into<String>, cost: f64) -> &mut Self {
+        self.manual.insert(system_name.into(), cost);
+        self
+    }
+
+    pub fn cost(&self, system_name: &str) -> f64 {
+        self.manual
+            .get(system_name)
+            .or_else(|| self.measured.get(system_name))
+            .copied()
+            .unwrap_or(1.0)
+    }
+}
+
+#[derive(Resource, Debug, Default, Clone)]
+pub struct ScheduleStats {
+    pub systems: usize,
+    /// Not counting the sets bevy makes for every system
+    pub sets: usize,
+    pub layers: usize,
+    /// The number of systems in the longest chain of dependencies
+    pub depth: usize,
+    /// The most systems that could ever run at the same time
+    pub max_parallelism: usize,
+    /// The most expensive chain of dependencies, no amount of threads can run faster than this
+    pub critical_path: Vec<NodeId>,
+    pub critical_path_cost: f64,
+    pub total_cost: f64,
+}
+
+impl ScheduleStats {
+    pub fn new(info: &ScheduleInfo, layer_graph: &LayerGraph, costs: &SystemCosts) -> Self {
+        let flattened = flatten_dependencies(&info.dependencies, &info.hierarchy);
+        let Ok(topsort) = topsort_graph(&flattened) else {
+            return ScheduleStats::default();
+        };
+        let closure = check_graph(&flattened, &topsort).transitive_closure;
+
+        let cost = |node: NodeId| costs.cost(&info.full_names[&node]);
+        let (depth, _) = longest_path(&flattened, &topsort, |_| 1.0);
+        let (critical_path_cost, critical_path) = longest_path(&flattened, &topsort, cost);
+
+        ScheduleStats {
+            systems: flattened.node_count(),
+            sets: info
+                .names
+                .keys()
+                .filter(|&&n| n.is_set() && !info.system_type_sets.contains(&n))
+                .count(),
+            layers: layer_graph.layers.len(),
+            depth: depth as usize,
+            max_parallelism: max_antichain_width(&closure),
+            critical_path,
+            critical_path_cost,
+            total_cost: topsort.iter().map(|&n| cost(n)).sum(),
+        }
+    }
+
+    /// How much faster than running every system one after another this could go with unlimited
+    /// threads
+    pub fn max_speedup(&self) -> f64 {
+        if self.critical_path_cost > 0.0 {
+            self.total_cost / self.critical_path_cost
+        } else {
+            1.0
+        }
+    }
+}
+
+impl fmt::Display for ScheduleStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Systems: {}", self.systems)?;
+        writeln!(f, "Sets: {}", self.sets)?;
+        writeln!(f, "Layers: {}", self.layers)?;
+        writeln!(f, "Depth: {}", self.depth)?;
+        writeln!(f, "Max parallelism: {}", self.max_parallelism)?;
+        writeln!(
+            f,
+            "Critical path: {} systems, cost {:.2} of {:.2}",
+            self.critical_path.len(),
+            self.critical_path_cost,
+            self.total_cost
+        )?;
+        write!(f, "Max speedup: {:.2}x", self.max_speedup())
+    }
+}
+
+pub fn update_schedule_stats(
+    info: Res<ScheduleInfo>,
+    layer_graph: Res<LayerGraph>,
+    costs: Res<SystemCosts>,
+    mut stats: ResMut<ScheduleStats>,
+) {
+    if info.is_changed() || layer_graph.is_changed() || costs.is_changed() {
+        *stats = ScheduleStats::new(&info, &layer_graph, &costs);
+    }
+}
+
+pub fn update_measured_costs(
+    timings: Res<SystemTimings>,
+    info: Res<ScheduleInfo>,
+    mut costs: ResMut<SystemCosts>,
+) {
+    if !timings.is_changed() || timings.0.is_empty() {
+        return;
+    }
+
+    costs.measured = timings
+        .0
+        .iter()
+        .filter(|((schedule, _), _)| *schedule == info.label)
+        .map(|((_, system), timing)| (system.clone(), timing.ms_per_frame()))
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::any::type_name_of_val;
+
+    use super::*;
+    use crate::schedule_graph::{schedule_graph_layout, LayoutSettings};
+
+    #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+    struct Stage;
+
+    fn first() {}
+    fn second() {}
+    fn third() {}
+    fn fourth() {}
+
+    #[test]
+    fn schedule_stats() {
+        let mut app = App::new();
+        app.add_systems(
+            Update,
+            ((first, second).chain(), (third, fourth).in_set(Stage)),
+        );
+        app.update();
+        let schedule = app.world.resource::<Schedules>().get(Update).unwrap();
+        let (layer_graph, info) =
+            schedule_graph_layout(schedule, &LayoutSettings::default()).unwrap();
+        let mut costs = SystemCosts::default();
+        costs.set(type_name_of_val(&first), 3.0);
+
+        let stats = ScheduleStats::new(&info, &layer_graph, &costs);
+        let name = |node| info.full_names[&node].rsplit("::").next().unwrap();
+        assert_eq!(stats.systems, 4);
+        assert_eq!(stats.sets, 1);
+        assert_eq!(stats.layers, layer_graph.layers.len());
+        assert_eq!(stats.depth, 2);
+        assert_eq!(stats.max_parallelism, 3);
+        assert_eq!(
+            stats
+                .critical_path
+                .iter()
+                .map(|&n| name(n))
+                .collect::<Vec<_>>(),
+            ["first", "second"]
+        );
+        assert_eq!(stats.critical_path_cost, 4.0);
+        assert_eq!(stats.total_cost, 6.0);
+        assert_eq!(stats.max_speedup(), 1.5);
+    }
+
+    #[test]
+    fn manual_costs_win_over_measured() {
+        let mut costs = SystemCosts::default();
+        costs.measured.insert("a".to_string(), 2.0);
+        costs.measured.insert("b".to_string(), 2.0);
+        costs.set("b", 5.0);
+        assert_eq!(costs.cost("a"), 2.0);
+        assert_eq!(costs.cost("b"), 5.0);
+        assert_eq!(costs.cost("c"), 1.0);
+    }
+}
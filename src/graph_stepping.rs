@@ -0,0 +1,144 @@
+use bevy::{
+    ecs::schedule::{InternedScheduleLabel, Stepping},
+    prelude::*,
+    utils::HashMap,
+};
+
+use crate::{
+    graph_ui::GraphNode,
+    layer_graph::{DrawnNode, LayerGraph},
+    schedule_graph::ScheduleInfo,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepBehaviour {
+    /// Stop before running it (ctrl click)
+    Break,
+    /// Skip it while stepping (alt click)
+    NeverRun,
+}
+
+/// Mirrors what has been set on bevy's `Stepping` for the graphed schedule, which can't be read
+/// back out of it
+#[derive(Resource, Debug, Default)]
+pub struct GraphStepping {
+    pub enabled: bool,
+    /// The drawn nodes the stepping cursor is on, it's about to run the first system in them
+    pub cursor: Vec<DrawnNode>,
+    pub behaviours: HashMap<DrawnNode, StepBehaviour>,
+}
+
+#[derive(Component)]
+pub struct SteppingText;
+
+/// Adds the graphed schedule to bevy's `Stepping` (creating it if the app hasn't)
+pub fn add_stepping_schedule(app: &mut App) {
+    let schedule = app.world.resource::<ScheduleInfo>().schedule;
+    app.world
+        .get_resource_or_insert_with(Stepping::new)
+        .add_schedule(schedule);
+}
+
+/// Steps `new` instead of `old`, forgetting the breakpoints in `old`
+pub fn switch_stepping_schedule(
+    world: &mut World,
+    old: InternedScheduleLabel,
+    new: InternedScheduleLabel,
+) {
+    if let Some(mut stepping) = world.get_resource_mut::<Stepping>() {
+        stepping.remove_schedule(old).add_schedule(new);
+    }
+    let mut graph_stepping = world.resource_mut::<GraphStepping>();
+    graph_stepping.behaviours.clear();
+    graph_stepping.cursor.clear();
+}
+
+pub fn stepping_controls(
+    keys: Res<ButtonInput<KeyCode>>,
+    nodes: Query<(&Interaction, &GraphNode), Changed<Interaction>>,
+    info: Res<ScheduleInfo>,
+    mut stepping: ResMut<Stepping>,
+    mut graph_stepping: ResMut<GraphStepping>,
+) {
+    if keys.just_pressed(KeyCode::KeyP) {
+        if stepping.is_enabled() {
+            stepping.disable();
+        } else {
+            stepping.enable();
+        }
+    }
+    if keys.just_pressed(KeyCode::KeyN) {
+        stepping.step_frame();
+    }
+    if keys.just_pressed(KeyCode::Enter) {
+        stepping.continue_frame();
+    }
+
+    let behaviour = if keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        StepBehaviour::Break
+    } else if keys.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]) {
+        StepBehaviour::NeverRun
+    } else {
+        return;
+    };
+    for (interaction, node) in &nodes {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+
+        // Clicking again with the same modifier clears it
+        let cleared = graph_stepping.behaviours.get(&node.0) == Some(&behaviour);
+        if cleared {
+            graph_stepping.behaviours.remove(&node.0);
+        } else {
+            graph_stepping.behaviours.insert(node.0, behaviour);
+        }
+        // Sets are drawn as one node so apply it to all of their systems
+        for system in info.drawn_systems(node.0) {
+            match behaviour {
+                _ if cleared => stepping.clear_node(info.schedule, system),
+                StepBehaviour::Break => stepping.set_breakpoint_node(info.schedule, system),
+                StepBehaviour::NeverRun => stepping.never_run_node(info.schedule, system),
+            };
+        }
+    }
+}
+
+pub fn show_stepping(
+    stepping: Res<Stepping>,
+    info: Res<ScheduleInfo>,
+    layer_graph: Res<LayerGraph>,
+    mut graph_stepping: ResMut<GraphStepping>,
+    mut text: Query<&mut Text, With<SteppingText>>,
+) {
+    if !stepping.is_changed() {
+        return;
+    }
+
+    let cursor = stepping
+        .cursor()
+        .filter(|(schedule, _)| *schedule == info.schedule)
+        .map(|(_, node)| node);
+    let enabled = stepping.is_enabled();
+    let new_cursor = cursor.map_or(Vec::new(), |node| layer_graph.displayed_as(&info, node));
+    if graph_stepping.enabled != enabled || graph_stepping.cursor != new_cursor {
+        graph_stepping.enabled = enabled;
+        graph_stepping.cursor = new_cursor;
+    }
+
+    let description = if !enabled {
+        "Stepping off (P to start, ctrl click for a breakpoint, alt click to skip)".to_string()
+    } else {
+        let position = match cursor {
+            Some(node) => format!("before {}", info.name(node)),
+            None => match stepping.cursor() {
+                Some((schedule, _)) => format!("in {:?}", schedule),
+                None => "at the end of the frame".to_string(),
+            },
+        };
+        format!("Stepping {position} (N for the next system, Enter to continue, P to stop)")
+    };
+    for mut text in &mut text {
+        text.sections[0].value = description.clone();
+    }
+}
@@ -1,41 +1,256 @@
 use bevy::{
     prelude::*,
-    render::camera::RenderTarget,
+    render::{camera::RenderTarget, view::RenderLayers},
     text::{FontAtlasSets, TextPipeline, TextSettings, YAxisOrientation},
+    ui::TargetCamera,
+    utils::HashMap,
     window::WindowRef,
 };
 
-use crate::layer_graph::LayerGraph;
+use crate::{
+    annotations::TagFilter,
+    frame_runs::{FrameRuns, FrameRunsText},
+    graph_stats::ScheduleStats,
+    graph_stepping::{GraphStepping, StepBehaviour, SteppingText},
+    layer_graph::{DrawnNode, LayerGraph},
+    ordering_explain::explain_systems_ordering,
+    schedule_graph::ScheduleInfo,
+    states_view::StatesView,
+    system_timing::{timing_layer_installed, SystemTimings},
+};
+
+const NODE_COLOUR: Color = Color::rgb(0.65, 0.65, 0.65);
+const SELECTED_COLOUR: Color = Color::rgb(0.3, 0.5, 0.9);
+const PATH_COLOUR: Color = Color::rgb(0.9, 0.6, 0.2);
+const CRITICAL_COLOUR: Color = Color::rgb(0.8, 0.25, 0.25);
+const SKIPPED_COLOUR: Color = Color::rgb(0.3, 0.3, 0.3);
+const CURSOR_COLOUR: Color = Color::rgb(0.9, 0.8, 0.2);
+const BREAKPOINT_COLOUR: Color = Color::rgb(0.6, 0.3, 0.8);
+const NEVER_RUN_COLOUR: Color = Color::rgb(0.2, 0.2, 0.35);
+const STATE_FILTER_COLOUR: Color = Color::rgb(0.2, 0.6, 0.6);
+const TAG_FILTER_COLOUR: Color = Color::rgb(0.85, 0.45, 0.65);
+const EMPTY_SET_COLOUR: Color = Color::rgba(0.65, 0.65, 0.65, 0.3);
+const SET_EDGE_COLOUR: Color = Color::rgb(0.95, 0.8, 0.4);
+/// Only the graph's camera draws the lines between sets
+pub const GRAPH_RENDER_LAYER: u8 = 7;
+// Outlines systems made of other systems
+const COMPOUND_BORDER_COLOUR: Color = Color::rgb(0.95, 0.95, 0.95);
+// Room for the timings after each node's name
+const TIMING_WIDTH: f32 = 80.0;
+// Room for the run counts after each node's name
+const RUNS_WIDTH: f32 = 90.0;
 
 #[derive(Component)]
-struct ScheduleGraphWindow;
+pub struct ScheduleGraphWindow;
 
-pub fn setup(
-    mut commands: Commands,
-    layer_graph: Res<LayerGraph>,
-    mut text_pipeline: ResMut<TextPipeline>,
-    fonts: Res<Assets<Font>>,
-    mut font_atlas_sets: ResMut<FontAtlasSets>,
-    mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
-    mut textures: ResMut<Assets<Image>>,
-    text_settings: Res<TextSettings>,
-) {
+/// Run condition for keyboard shortcuts, which only apply while the graph's window has focus so
+/// they don't also act on keys meant for the app (like WASD)
+pub fn graph_window_focused(windows: Query<&Window, With<ScheduleGraphWindow>>) -> bool {
+    windows.iter().any(|window| window.focused)
+}
+
+#[derive(Component)]
+pub struct ScheduleGraphCamera;
+
+/// The lines for ordering between sets
+#[derive(Default, Reflect, GizmoConfigGroup)]
+pub struct SetEdgeGizmos;
+
+/// The parent of all the drawn nodes
+#[derive(Component)]
+pub struct GraphRoot;
+
+#[derive(Component)]
+pub struct GraphNode(pub DrawnNode);
+
+#[derive(Component)]
+pub struct GraphNodeLabel(pub DrawnNode);
+
+#[derive(Component)]
+pub struct ExplanationText;
+
+/// Shows the full name of the node under the cursor
+#[derive(Component)]
+pub struct FullNameText;
+
+/// The last two nodes clicked on, oldest first
+#[derive(Resource, Default)]
+pub struct NodeSelection(pub Vec<DrawnNode>);
+
+impl NodeSelection {
+    pub fn select(&mut self, node: DrawnNode) {
+        self.0.retain(|n| *n != node);
+        self.0.push(node);
+        if self.0.len() > 2 {
+            self.0.remove(0);
+        }
+    }
+}
+
+/// The drawn nodes on the path explaining the selection's ordering
+#[derive(Resource, Default)]
+pub struct HighlightedPath(pub Vec<DrawnNode>);
+
+#[derive(Component)]
+pub struct StatsText;
+
+/// The drawn nodes on the critical path, empty while it is hidden (toggle with C)
+#[derive(Resource, Default)]
+pub struct CriticalPath {
+    pub shown: bool,
+    pub nodes: Vec<DrawnNode>,
+}
+
+pub fn setup(mut commands: Commands) {
     let window_comp = Window {
         title: "Schedule Graph".to_string(),
         ..default()
     };
-    let scale_factor = window_comp.resolution.scale_factor();
     // Window
     let window = WindowRef::Entity(commands.spawn((window_comp, ScheduleGraphWindow)).id());
 
     // Camera
-    commands.spawn(Camera2dBundle {
-        camera: Camera {
-            target: RenderTarget::Window(window),
+    let camera = commands
+        .spawn((
+            Camera2dBundle {
+                camera: Camera {
+                    target: RenderTarget::Window(window),
+                    ..default()
+                },
+                ..default()
+            },
+            ScheduleGraphCamera,
+            RenderLayers::default().with(GRAPH_RENDER_LAYER),
+        ))
+        .id();
+
+    commands.spawn((
+        TextBundle::from_section(
+            "Click two nodes to see what orders them",
+            TextStyle {
+                font_size: 20.0,
+                color: Color::WHITE,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(5.0),
+            left: Val::Px(10.0),
+            ..default()
+        }),
+        ExplanationText,
+        TargetCamera(camera),
+    ));
+
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 20.0,
+                color: Color::WHITE,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(5.0),
+            right: Val::Px(10.0),
+            ..default()
+        }),
+        StatsText,
+        TargetCamera(camera),
+    ));
+
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 20.0,
+                color: Color::WHITE,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(5.0),
+            right: Val::Px(10.0),
+            ..default()
+        }),
+        FrameRunsText,
+        TargetCamera(camera),
+    ));
+
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 20.0,
+                color: Color::WHITE,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(5.0),
+            left: Val::Px(10.0),
+            ..default()
+        }),
+        SteppingText,
+        TargetCamera(camera),
+    ));
+
+    commands.spawn((
+        TextBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font_size: 18.0,
+                    color: Color::WHITE,
+                    ..default()
+                },
+            ),
+            style: Style {
+                position_type: PositionType::Absolute,
+                padding: UiRect::axes(Val::Px(5.0), Val::Px(2.0)),
+                ..default()
+            },
+            background_color: Color::rgba(0.0, 0.0, 0.0, 0.8).into(),
+            visibility: Visibility::Hidden,
+            z_index: ZIndex::Global(1),
             ..default()
         },
-        ..default()
-    });
+        FullNameText,
+        TargetCamera(camera),
+    ));
+}
+
+/// Draws the graph's nodes, again whenever the layout changes
+#[allow(clippy::too_many_arguments)]
+pub fn draw_graph(
+    mut commands: Commands,
+    layer_graph: Res<LayerGraph>,
+    info: Res<ScheduleInfo>,
+    windows: Query<&Window, With<ScheduleGraphWindow>>,
+    cameras: Query<Entity, With<ScheduleGraphCamera>>,
+    roots: Query<Entity, With<GraphRoot>>,
+    mut text_pipeline: ResMut<TextPipeline>,
+    fonts: Res<Assets<Font>>,
+    mut font_atlas_sets: ResMut<FontAtlasSets>,
+    mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
+    mut textures: ResMut<Assets<Image>>,
+    text_settings: Res<TextSettings>,
+) {
+    let (Ok(window), Ok(camera)) = (windows.get_single(), cameras.get_single()) else {
+        return;
+    };
+    if !layer_graph.is_changed() && !roots.is_empty() {
+        return;
+    }
+    let scale_factor = window.resolution.scale_factor();
+    for root in &roots {
+        commands.entity(root).despawn_recursive();
+    }
 
     // commands
     //     .spawn(NodeBundle {
@@ -87,13 +302,17 @@ pub fn setup(
     //     });
 
     commands
-        .spawn(NodeBundle {
-            style: Style {
-                position_type: PositionType::Absolute,
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    ..default()
+                },
                 ..default()
             },
-            ..default()
-        })
+            GraphRoot,
+            TargetCamera(camera),
+        ))
         .with_children(|builder| {
             let mut x_placement = 10.0;
             for layer in &layer_graph.layers {
@@ -126,36 +345,409 @@ pub fn setup(
                     };
 
                     layer_width = layer_width.max(text_width);
+                    let compound = node
+                        .node()
+                        .is_some_and(|node| info.compound_systems.contains(&node));
 
                     builder
-                        .spawn(NodeBundle {
-                            style: Style {
-                                position_type: PositionType::Absolute,
-                                margin: UiRect::top(Val::Px(5.)),
-                                padding: UiRect::axes(Val::Px(5.), Val::Px(1.)),
-                                left: Val::Px(x_placement),
-                                top: Val::Px(y as f32 * 35.0 + 5.0),
+                        .spawn((
+                            NodeBundle {
+                                style: Style {
+                                    position_type: PositionType::Absolute,
+                                    margin: UiRect::top(Val::Px(5.)),
+                                    padding: UiRect::axes(Val::Px(5.), Val::Px(1.)),
+                                    left: Val::Px(x_placement),
+                                    top: Val::Px(y as f32 * 35.0 + 5.0),
+                                    border: UiRect::all(Val::Px(if compound { 2. } else { 0. })),
+                                    ..default()
+                                },
+                                background_color: node_colour(&info, *node).into(),
+                                border_color: COMPOUND_BORDER_COLOUR.into(),
                                 ..default()
                             },
-                            background_color: Color::rgb(0.65, 0.65, 0.65).into(),
-                            ..default()
-                        })
+                            Interaction::default(),
+                            GraphNode(*node),
+                        ))
                         .with_children(|builder| {
-                            builder.spawn(
-                                TextBundle::from_section(
-                                    layer_graph.node_name(node),
-                                    TextStyle {
-                                        font_size: 24.0,
-                                        color: Color::WHITE,
-                                        ..default()
-                                    },
-                                )
+                            builder.spawn((
+                                TextBundle::from_sections([
+                                    TextSection::new(
+                                        layer_graph.node_name(node),
+                                        TextStyle {
+                                            font_size: 24.0,
+                                            color: Color::WHITE,
+                                            ..default()
+                                        },
+                                    ),
+                                    // Filled in by the timing overlay
+                                    TextSection::new(
+                                        "",
+                                        TextStyle {
+                                            font_size: 18.0,
+                                            color: Color::WHITE,
+                                            ..default()
+                                        },
+                                    ),
+                                    // Filled in with how often it ran
+                                    TextSection::new(
+                                        "",
+                                        TextStyle {
+                                            font_size: 18.0,
+                                            color: Color::WHITE,
+                                            ..default()
+                                        },
+                                    ),
+                                ])
                                 .with_no_wrap(),
-                            );
+                                GraphNodeLabel(*node),
+                            ));
                         });
                 }
 
-                x_placement += layer_width / 1.2 + 10.0;
+                x_placement += layer_width / 1.2 + 10.0 + RUNS_WIDTH;
+                if timing_layer_installed() {
+                    x_placement += TIMING_WIDTH;
+                }
             }
         });
 }
+
+pub fn select_nodes(
+    nodes: Query<(&Interaction, &GraphNode), Changed<Interaction>>,
+    mut selection: ResMut<NodeSelection>,
+) {
+    for (interaction, node) in &nodes {
+        if *interaction == Interaction::Pressed {
+            selection.select(node.0);
+        }
+    }
+}
+
+/// However the nodes are labelled, hovering one shows its full name
+pub fn show_full_name(
+    nodes: Query<(Ref<Interaction>, &GraphNode)>,
+    info: Res<ScheduleInfo>,
+    layer_graph: Res<LayerGraph>,
+    windows: Query<&Window, With<ScheduleGraphWindow>>,
+    mut tooltip: Query<(&mut Text, &mut Style, &mut Visibility), With<FullNameText>>,
+) {
+    if !nodes
+        .iter()
+        .any(|(interaction, _)| interaction.is_changed())
+    {
+        return;
+    }
+
+    let hovered = nodes
+        .iter()
+        .find(|(interaction, _)| **interaction != Interaction::None)
+        .map(|(_, node)| node.0);
+    let cursor = windows.get_single().ok().and_then(Window::cursor_position);
+    for (mut text, mut style, mut visibility) in &mut tooltip {
+        match (hovered, cursor) {
+            (Some(node), Some(cursor)) => {
+                let mut description = info.drawn_full_name(node);
+                // Ordering that was on a set it's drawn without
+                let mut collapsed: Vec<_> = info
+                    .collapsed_sets
+                    .iter()
+                    .flat_map(|(&set, edges)| edges.iter().map(move |&edge| (set, edge)))
+                    .filter_map(|(set, (a, b))| {
+                        match (DrawnNode::Node(a) == node, DrawnNode::Node(b) == node) {
+                            (true, _) => {
+                                Some(format!("{}.before({})", info.name(set), info.name(b)))
+                            }
+                            (_, true) => {
+                                Some(format!("{}.after({})", info.name(set), info.name(a)))
+                            }
+                            _ => None,
+                        }
+                    })
+                    .collect();
+                collapsed.sort();
+                for line in collapsed {
+                    description.push_str("\nFrom collapsed set: ");
+                    description.push_str(&line);
+                }
+                if let Some(annotation) = node.node().and_then(|node| info.annotations.get(&node)) {
+                    if let Some(note) = &annotation.note {
+                        description.push('\n');
+                        description.push_str(note);
+                    }
+                    if !annotation.tags.is_empty() {
+                        description.push_str(&format!("\nTags: {}", annotation.tags.join(", ")));
+                    }
+                }
+                // How what's inside a set is laid out, a column per line
+                if let Some(inner) = layer_graph.inner(&node) {
+                    description.push_str("\nInside:");
+                    for layer in &inner.layers {
+                        let mut names: Vec<_> = layer.iter().map(|n| inner.node_name(n)).collect();
+                        names.sort();
+                        description.push_str(&format!("\n  {}", names.join(", ")));
+                    }
+                }
+                text.sections[0].value = description;
+                style.left = Val::Px(cursor.x + 15.0);
+                style.top = Val::Px(cursor.y + 15.0);
+                *visibility = Visibility::Visible;
+            }
+            _ => *visibility = Visibility::Hidden,
+        }
+    }
+}
+
+pub fn explain_selection(
+    selection: Res<NodeSelection>,
+    layer_graph: Res<LayerGraph>,
+    info: Res<ScheduleInfo>,
+    mut highlighted: ResMut<HighlightedPath>,
+    mut text: Query<&mut Text, With<ExplanationText>>,
+) {
+    if !selection.is_changed() {
+        return;
+    }
+
+    highlighted.0.clear();
+    let description = match selection.0[..] {
+        [a, b] => {
+            let info = info.as_ref();
+            let (a_systems, b_systems) = (info.drawn_systems(a), info.drawn_systems(b));
+            let explanation = explain_systems_ordering(info, &a_systems, &b_systems)
+                .map(|e| (e, a, b))
+                .or_else(|| {
+                    explain_systems_ordering(info, &b_systems, &a_systems).map(|e| (e, b, a))
+                });
+
+            match explanation {
+                Some((explanation, first, second)) => {
+                    for node in explanation.path() {
+                        highlighted.0.extend(layer_graph.displayed_as(info, node));
+                    }
+                    format!(
+                        "Why does {} run before {}?\n{}",
+                        info.drawn_name(first),
+                        info.drawn_name(second),
+                        explanation.describe(info)
+                    )
+                }
+                None => format!(
+                    "Nothing orders {} and {}",
+                    info.drawn_name(a),
+                    info.drawn_name(b)
+                ),
+            }
+        }
+        [_] => "Click another node to see what orders them".to_string(),
+        _ => "Click two nodes to see what orders them".to_string(),
+    };
+
+    for mut text in &mut text {
+        text.sections[0].value = description.clone();
+    }
+}
+
+pub fn show_stats(
+    stats: Res<ScheduleStats>,
+    tag_filter: Res<TagFilter>,
+    mut text: Query<&mut Text, With<StatsText>>,
+) {
+    if !stats.is_changed() && !tag_filter.is_changed() {
+        return;
+    }
+    let tag = match &tag_filter.tag {
+        Some(tag) => format!("\nShowing systems tagged {tag} (A for the next tag)"),
+        None => String::new(),
+    };
+    for mut text in &mut text {
+        text.sections[0].value = format!("{}\n(C to show the critical path){tag}", *stats);
+    }
+}
+
+pub fn toggle_critical_path(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut critical_path: ResMut<CriticalPath>,
+) {
+    if keys.just_pressed(KeyCode::KeyC) {
+        critical_path.shown = !critical_path.shown;
+    }
+}
+
+pub fn highlight_critical_path(
+    stats: Res<ScheduleStats>,
+    layer_graph: Res<LayerGraph>,
+    info: Res<ScheduleInfo>,
+    mut critical_path: ResMut<CriticalPath>,
+) {
+    if !stats.is_changed() && !critical_path.is_changed() {
+        return;
+    }
+
+    critical_path.nodes.clear();
+    if critical_path.shown {
+        for &node in &stats.critical_path {
+            critical_path
+                .nodes
+                .extend(layer_graph.displayed_as(&info, node));
+        }
+    }
+}
+
+/// The time spent in each drawn node per frame, for the displayed schedule
+#[derive(Resource, Default)]
+pub struct NodeTimings(pub HashMap<DrawnNode, f64>);
+
+pub fn show_system_timings(
+    timings: Res<SystemTimings>,
+    layer_graph: Res<LayerGraph>,
+    info: Res<ScheduleInfo>,
+    mut node_timings: ResMut<NodeTimings>,
+    mut labels: Query<(&GraphNodeLabel, &mut Text)>,
+) {
+    if !timings.is_changed() {
+        return;
+    }
+
+    node_timings.0.clear();
+    for layer in &layer_graph.layers {
+        for &node in layer {
+            // Sets are drawn as one node so show the time spent in all of their systems
+            let times: Vec<_> = info
+                .drawn_systems(node)
+                .into_iter()
+                .filter_map(|system| timings.get(&info.label, &info.full_names[&system]))
+                .map(|timing| timing.ms_per_frame())
+                .collect();
+            if !times.is_empty() {
+                node_timings.0.insert(node, times.iter().sum());
+            }
+        }
+    }
+
+    for (label, mut text) in &mut labels {
+        text.sections[1].value = match node_timings.0.get(&label.0) {
+            Some(ms) => format!(" {ms:.2}ms"),
+            None => String::new(),
+        };
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn colour_nodes(
+    mut nodes: Query<(&GraphNode, &mut BackgroundColor)>,
+    selection: Res<NodeSelection>,
+    highlighted: Res<HighlightedPath>,
+    critical_path: Res<CriticalPath>,
+    node_timings: Res<NodeTimings>,
+    info: Res<ScheduleInfo>,
+    frame_runs: Res<FrameRuns>,
+    stepping: Res<GraphStepping>,
+    states_view: Res<StatesView>,
+    tag_filter: Res<TagFilter>,
+) {
+    if !selection.is_changed()
+        && !highlighted.is_changed()
+        && !critical_path.is_changed()
+        && !node_timings.is_changed()
+        && !frame_runs.is_changed()
+        && !stepping.is_changed()
+        && !states_view.is_changed()
+        && !tag_filter.is_changed()
+    {
+        return;
+    }
+
+    let slowest = node_timings.0.values().copied().fold(0.0, f64::max);
+    for (node, mut colour) in &mut nodes {
+        *colour = if stepping.cursor.contains(&node.0) {
+            CURSOR_COLOUR
+        } else if selection.0.contains(&node.0) {
+            SELECTED_COLOUR
+        } else if highlighted.0.contains(&node.0) {
+            PATH_COLOUR
+        } else if critical_path.nodes.contains(&node.0) {
+            CRITICAL_COLOUR
+        } else if states_view.filtered.contains(&node.0) {
+            STATE_FILTER_COLOUR
+        } else if tag_filter.filtered.contains(&node.0) {
+            TAG_FILTER_COLOUR
+        } else if let Some(behaviour) = stepping.behaviours.get(&node.0) {
+            match behaviour {
+                StepBehaviour::Break => BREAKPOINT_COLOUR,
+                StepBehaviour::NeverRun => NEVER_RUN_COLOUR,
+            }
+        } else if node
+            .0
+            .node()
+            .is_some_and(|set| info.empty_sets.contains(&set))
+        {
+            node_colour(&info, node.0)
+        } else if frame_runs.schedule_runs > 0
+            && info
+                .drawn_systems(node.0)
+                .iter()
+                .all(|&s| frame_runs.ran(s) == 0)
+        {
+            SKIPPED_COLOUR
+        } else if let Some(&ms) = node_timings.0.get(&node.0) {
+            // Green for the fastest through to red for the slowest
+            let t = if slowest > 0.0 {
+                (ms / slowest) as f32
+            } else {
+                0.0
+            };
+            Color::rgb(0.2 + 0.6 * t, 0.7 - 0.5 * t, 0.2)
+        } else {
+            node_colour(&info, node.0)
+        }
+        .into();
+    }
+}
+
+/// The colour the app gave it, or the default
+fn node_colour(info: &ScheduleInfo, node: DrawnNode) -> Color {
+    let Some(node) = node.node() else {
+        return NODE_COLOUR;
+    };
+    match info.annotations.get(&node).and_then(|a| a.color) {
+        Some(colour) => colour,
+        None if info.empty_sets.contains(&node) => EMPTY_SET_COLOUR,
+        None => NODE_COLOUR,
+    }
+}
+
+/// Arrows from the right of a set to the left of the sets it's ordered before
+pub fn draw_set_edges(
+    mut gizmos: Gizmos<SetEdgeGizmos>,
+    layer_graph: Res<LayerGraph>,
+    nodes: Query<(&GraphNode, &Node, &GlobalTransform)>,
+    windows: Query<&Window, With<ScheduleGraphWindow>>,
+) {
+    if layer_graph.set_edges.is_empty() {
+        return;
+    }
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    let mut bounds = HashMap::new();
+    for (node, ui_node, transform) in &nodes {
+        // UI positions are from the top left, the camera's from the middle going up
+        let centre = transform.translation().truncate();
+        let centre = Vec2::new(
+            centre.x - window.width() / 2.0,
+            window.height() / 2.0 - centre.y,
+        );
+        bounds.insert(node.0, (centre, ui_node.size()));
+    }
+    for (a, b) in &layer_graph.set_edges {
+        let (Some(&(a_centre, a_size)), Some(&(b_centre, b_size))) = (bounds.get(a), bounds.get(b))
+        else {
+            continue;
+        };
+        gizmos.arrow_2d(
+            a_centre + Vec2::X * a_size.x / 2.0,
+            b_centre - Vec2::X * b_size.x / 2.0,
+            SET_EDGE_COLOUR,
+        );
+    }
+}
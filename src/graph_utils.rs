@@ -4,6 +4,7 @@ use bevy::{
         petgraph::{
             algo::TarjanScc,
             graphmap::{DiGraphMap, NodeTrait},
+            visit::Dfs,
             Direction::{Incoming, Outgoing},
         },
         HashMap, HashSet,
@@ -161,7 +162,9 @@ impl<V: NodeTrait + Debug> Default for CheckGraphResults<V> {
 }
 
 // More privatisem
-pub fn topsort_graph(graph: &DiGraphMap<NodeId, ()>) -> Result<Vec<NodeId>, ScheduleBuildError> {
+pub fn topsort_graph<V: NodeTrait + Debug>(
+    graph: &DiGraphMap<V, ()>,
+) -> Result<Vec<V>, ScheduleBuildError> {
     // Tarjan's SCC algorithm returns elements in *reverse* topological order.
     let mut tarjan_scc = TarjanScc::new();
     let mut top_sorted_nodes = Vec::with_capacity(graph.node_count());
@@ -182,6 +185,278 @@ pub fn topsort_graph(graph: &DiGraphMap<NodeId, ()>) -> Result<Vec<NodeId>, Sche
         top_sorted_nodes.reverse();
         Ok(top_sorted_nodes)
     } else {
-        unreachable!()
+        Err(ScheduleBuildError::DependencyCycle(format!(
+            "{:?}",
+            sccs_with_cycles
+        )))
+    }
+}
+
+/// Returns every group of nodes that can all reach each other, i.e. each cycle in the graph.
+pub fn find_cycles<V: NodeTrait>(graph: &DiGraphMap<V, ()>) -> Vec<Vec<V>> {
+    let mut tarjan_scc = TarjanScc::new();
+    let mut sccs_with_cycles = Vec::new();
+
+    tarjan_scc.run(graph, |scc| {
+        if scc.len() > 1 || graph.contains_edge(scc[0], scc[0]) {
+            sccs_with_cycles.push(scc.to_vec());
+        }
+    });
+
+    sccs_with_cycles
+}
+
+/// Removes a node, keeping everything that was ordered through it ordered
+pub fn contract_node(graph: &mut DiGraphMap<NodeId, ()>, node: NodeId) {
+    let incoming: Vec<_> = graph.neighbors_directed(node, Incoming).collect();
+    let outgoing: Vec<_> = graph.neighbors_directed(node, Outgoing).collect();
+    for &a in &incoming {
+        for &b in &outgoing {
+            if a != b {
+                graph.add_edge(a, b, ());
+            }
+        }
+    }
+    graph.remove_node(node);
+}
+
+/// Replaces `members` with `group`, which gets all of their edges
+pub fn merge_nodes<V: NodeTrait>(graph: &mut DiGraphMap<V, ()>, members: &[V], group: V) {
+    let members: HashSet<_> = members.iter().copied().collect();
+    let edges: Vec<_> = graph
+        .all_edges()
+        .filter(|(a, b, _)| members.contains(a) || members.contains(b))
+        .map(|(a, b, _)| (a, b))
+        .collect();
+    graph.add_node(group);
+    for (a, b) in edges {
+        let a = if members.contains(&a) { group } else { a };
+        let b = if members.contains(&b) { group } else { b };
+        if a != b {
+            graph.add_edge(a, b, ());
+        }
+    }
+    for member in members {
+        graph.remove_node(member);
+    }
+}
+
+/// Returns the node itself for systems, or every system (transitively) inside it for sets.
+pub fn set_systems(hierarchy: &DiGraphMap<NodeId, ()>, node: NodeId) -> Vec<NodeId> {
+    if node.is_system() {
+        return vec![node];
+    }
+    if !hierarchy.contains_node(node) {
+        return Vec::new();
+    }
+
+    let mut systems = Vec::new();
+    let mut dfs = Dfs::new(hierarchy, node);
+    while let Some(child) = dfs.next(hierarchy) {
+        if child.is_system() {
+            systems.push(child);
+        }
+    }
+    systems
+}
+
+/// Expands dependencies on sets into dependencies between the systems in them, like bevy does
+/// before running a schedule.
+pub fn flatten_dependencies(
+    dependencies: &DiGraphMap<NodeId, ()>,
+    hierarchy: &DiGraphMap<NodeId, ()>,
+) -> DiGraphMap<NodeId, ()> {
+    let mut flattened = DiGraphMap::new();
+    for node in dependencies.nodes().filter(NodeId::is_system) {
+        flattened.add_node(node);
+    }
+
+    let mut set_systems_cache = HashMap::new();
+    for (a, b, ()) in dependencies.all_edges() {
+        let a_systems = set_systems_cache
+            .entry(a)
+            .or_insert_with(|| set_systems(hierarchy, a))
+            .clone();
+        let b_systems = set_systems_cache
+            .entry(b)
+            .or_insert_with(|| set_systems(hierarchy, b));
+        for &a_system in &a_systems {
+            for &b_system in b_systems.iter() {
+                flattened.add_edge(a_system, b_system, ());
+            }
+        }
+    }
+
+    flattened
+}
+
+/// Breadth first search for the shortest path from any of `from` to any of `to`, including both
+/// ends.
+pub fn shortest_path<V: NodeTrait>(
+    graph: &DiGraphMap<V, ()>,
+    from: &[V],
+    to: &[V],
+) -> Option<Vec<V>> {
+    let mut previous = HashMap::new();
+    let mut queue: std::collections::VecDeque<_> = from.iter().copied().collect();
+    while let Some(node) = queue.pop_front() {
+        if to.contains(&node) {
+            let mut path = vec![node];
+            let mut current = node;
+            while let Some(&prev) = previous.get(&current) {
+                path.push(prev);
+                current = prev;
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for next in graph.neighbors_directed(node, Outgoing) {
+            if !from.contains(&next) && !previous.contains_key(&next) {
+                previous.insert(next, node);
+                queue.push_back(next);
+            }
+        }
+    }
+    None
+}
+
+/// The heaviest path through a DAG, and its total weight.
+pub fn longest_path<V: NodeTrait>(
+    graph: &DiGraphMap<V, ()>,
+    topological_order: &[V],
+    weight: impl Fn(V) -> f64,
+) -> (f64, Vec<V>) {
+    let mut best: HashMap<V, (f64, Option<V>)> = HashMap::with_capacity(topological_order.len());
+    for &node in topological_order {
+        let (before, previous) = graph
+            .neighbors_directed(node, Incoming)
+            .map(|pred| (best[&pred].0, Some(pred)))
+            .fold((0.0, None), |a, b| if b.0 > a.0 { b } else { a });
+        best.insert(node, (before + weight(node), previous));
+    }
+
+    let Some((&end, &(total, _))) = best.iter().max_by(|a, b| a.1 .0.total_cmp(&b.1 .0)) else {
+        return (0.0, Vec::new());
+    };
+    let mut path = vec![end];
+    while let Some(previous) = best[path.last().unwrap()].1 {
+        path.push(previous);
+    }
+    path.reverse();
+    (total, path)
+}
+
+/// The size of the largest set of nodes none of which can reach each other, so the most that can
+/// ever run at once.
+///
+/// By Dilworth's theorem this is the node count minus a maximum matching in the transitive closure.
+pub fn max_antichain_width<V: NodeTrait>(transitive_closure: &DiGraphMap<V, ()>) -> usize {
+    fn augment<V: NodeTrait>(
+        closure: &DiGraphMap<V, ()>,
+        node: V,
+        matched_to: &mut HashMap<V, V>,
+        seen: &mut HashSet<V>,
+    ) -> bool {
+        for next in closure.neighbors_directed(node, Outgoing) {
+            if seen.insert(next) {
+                let free = match matched_to.get(&next) {
+                    None => true,
+                    Some(&other) => augment(closure, other, matched_to, seen),
+                };
+                if free {
+                    matched_to.insert(next, node);
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    let mut matched_to = HashMap::new();
+    let mut matching = 0;
+    for node in transitive_closure.nodes() {
+        if augment(
+            transitive_closure,
+            node,
+            &mut matched_to,
+            &mut HashSet::new(),
+        ) {
+            matching += 1;
+        }
+    }
+    transitive_closure.node_count() - matching
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(edges: &[(u32, u32)]) -> (DiGraphMap<u32, ()>, Vec<u32>) {
+        let graph = DiGraphMap::from_edges(edges);
+        let topsort = topsort_graph(&graph).unwrap();
+        (graph, topsort)
+    }
+
+    fn width(edges: &[(u32, u32)]) -> usize {
+        let (graph, topsort) = graph(edges);
+        max_antichain_width(&check_graph(&graph, &topsort).transitive_closure)
+    }
+
+    #[test]
+    fn longest_path_through_chain() {
+        let (graph, topsort) = graph(&[(1, 2), (2, 3)]);
+        assert_eq!(
+            longest_path(&graph, &topsort, |_| 1.0),
+            (3.0, vec![1, 2, 3])
+        );
+    }
+
+    #[test]
+    fn longest_path_through_diamond() {
+        let (graph, topsort) = graph(&[(1, 2), (1, 3), (2, 4), (3, 4)]);
+        let (length, path) = longest_path(&graph, &topsort, |_| 1.0);
+        assert_eq!(length, 3.0);
+        assert_eq!((path.len(), path[0], path[2]), (3, 1, 4));
+    }
+
+    #[test]
+    fn longest_path_through_fan_out() {
+        let edges: Vec<_> = (1..=5).map(|n| (0, n)).collect();
+        let (graph, topsort) = graph(&edges);
+        let (length, path) = longest_path(&graph, &topsort, |_| 1.0);
+        assert_eq!(length, 2.0);
+        assert_eq!(path[0], 0);
+    }
+
+    #[test]
+    fn longest_path_weighted() {
+        // The branch through 3 has fewer steps but costs more
+        let (graph, topsort) = graph(&[(1, 2), (2, 4), (4, 5), (1, 3), (3, 5)]);
+        let weight = |node| if node == 3 { 10.0 } else { 1.0 };
+        assert_eq!(
+            longest_path(&graph, &topsort, weight),
+            (12.0, vec![1, 3, 5])
+        );
+        assert_eq!(
+            longest_path(&graph, &topsort, |_| 1.0),
+            (4.0, vec![1, 2, 4, 5])
+        );
+    }
+
+    #[test]
+    fn longest_path_of_nothing() {
+        let (graph, topsort) = graph(&[]);
+        assert_eq!(longest_path(&graph, &topsort, |_| 1.0), (0.0, vec![]));
+    }
+
+    #[test]
+    fn antichain_widths() {
+        assert_eq!(width(&[]), 0);
+        assert_eq!(width(&[(1, 2), (2, 3)]), 1);
+        assert_eq!(width(&[(1, 2), (1, 3), (2, 4), (3, 4)]), 2);
+        let fan_out: Vec<_> = (1..=5).map(|n| (0, n)).collect();
+        assert_eq!(width(&fan_out), 5);
+        // Two separate chains
+        assert_eq!(width(&[(1, 2), (2, 3), (4, 5)]), 2);
     }
 }
@@ -1,28 +1,85 @@
-use bevy::{ecs::schedule::NodeId, prelude::*, utils::HashMap};
+use bevy::{
+    ecs::schedule::NodeId,
+    prelude::*,
+    utils::{petgraph::Direction, HashMap},
+};
 
-#[derive(Debug)]
+use crate::schedule_graph::ScheduleInfo;
+
+/// A node as it's drawn: one of the schedule's own, or one standing in for several of its systems
+/// that bevy knows nothing about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DrawnNode {
+    Node(NodeId),
+    /// A crate's systems, see `ScheduleInfo::crate_groups`
+    CrateGroup(usize),
+    /// Systems that just follow one another, see `ScheduleInfo::chains`
+    Chain(usize),
+}
+
+impl DrawnNode {
+    /// The schedule's node, if it is one
+    pub fn node(self) -> Option<NodeId> {
+        match self {
+            DrawnNode::Node(node) => Some(node),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum LayerNode {
     System(String),
+    /// With how what's inside it is laid out
     Set(String, LayerGraph),
 }
 
-#[derive(Debug, Default, Resource)]
+#[derive(Debug, Default, Clone, Resource)]
 pub struct LayerGraph {
-    nodes: HashMap<NodeId, LayerNode>,
-    pub layers: Vec<Vec<NodeId>>,
-    edges: HashMap<NodeId, Vec<NodeId>>,
+    nodes: HashMap<DrawnNode, LayerNode>,
+    pub layers: Vec<Vec<DrawnNode>>,
+    edges: HashMap<DrawnNode, Vec<DrawnNode>>,
+    /// Ordering between sets themselves rather than their systems
+    pub set_edges: Vec<(DrawnNode, DrawnNode)>,
 }
 
 impl LayerGraph {
-    pub fn add_node(&mut self, node_id: NodeId, node: LayerNode) {
+    pub fn add_node(&mut self, node_id: DrawnNode, node: LayerNode) {
         self.nodes.insert(node_id, node);
     }
 
-    pub fn add_edges(&mut self, node_id: NodeId, edges: Vec<NodeId>) {
+    pub fn add_edges(&mut self, node_id: DrawnNode, edges: Vec<DrawnNode>) {
         self.edges.insert(node_id, edges);
     }
 
-    pub fn node_name(&self, node_id: &NodeId) -> String {
+    pub fn contains(&self, node_id: &DrawnNode) -> bool {
+        self.nodes.contains_key(node_id)
+    }
+
+    /// The drawn nodes that stand in for this one (itself, or the sets, crate group or chain it was
+    /// drawn inside)
+    pub fn displayed_as(&self, info: &ScheduleInfo, node_id: NodeId) -> Vec<DrawnNode> {
+        if self.contains(&DrawnNode::Node(node_id)) {
+            return vec![DrawnNode::Node(node_id)];
+        }
+        if let Some(&grouped) = info.grouped_systems.get(&node_id) {
+            return vec![grouped];
+        }
+        info.hierarchy
+            .neighbors_directed(node_id, Direction::Incoming)
+            .flat_map(|parent| self.displayed_as(info, parent))
+            .collect()
+    }
+
+    /// The layout of what's inside a set, if it has anything drawn inside it
+    pub fn inner(&self, node_id: &DrawnNode) -> Option<&LayerGraph> {
+        match self.nodes.get(node_id)? {
+            LayerNode::Set(_, inner) if !inner.layers.is_empty() => Some(inner),
+            _ => None,
+        }
+    }
+
+    pub fn node_name(&self, node_id: &DrawnNode) -> String {
         match &self.nodes[node_id] {
             LayerNode::System(name) => name.clone(),
             LayerNode::Set(name, _graph) => name.clone(),
@@ -1,14 +1,37 @@
 #![allow(dead_code)] // While prototyping
 
+mod annotations;
+mod chains;
+mod crate_groups;
+mod executor_sim;
+mod frame_runs;
+mod gantt;
+mod graph_asserts;
+mod graph_refresh;
+mod graph_stats;
+mod graph_stepping;
 mod graph_ui;
 mod graph_utils;
 mod layer_graph;
+mod ordering_explain;
+mod schedule_check;
 mod schedule_graph;
+mod schedule_order;
+mod set_display;
 mod shorten_type;
+mod states_view;
+mod sub_apps;
+mod system_timing;
 
-use bevy::prelude::*;
+use bevy::{app::PluginsState, log::LogPlugin, prelude::*};
 
-use crate::schedule_graph::ScheduleGraphPlugin;
+use crate::{
+    annotations::{AnnotateGraphExt, GraphAnnotation},
+    schedule_check::{run_schedule_check, BLESS_ENV_VAR, CHECK_ENV_VAR},
+    schedule_graph::ScheduleGraphPlugin,
+    states_view::StateGraphPlugin,
+    system_timing::timing_layer,
+};
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash, SystemSet)]
 struct TestSet;
@@ -20,6 +43,22 @@ fn test1() {}
 fn test2() {}
 fn test3() {}
 fn test4() {}
+fn produce() -> u32 {
+    1
+}
+fn consume(_: In<u32>) {}
+
+#[derive(States, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+enum TestState {
+    #[default]
+    Menu,
+    Playing,
+}
+
+fn start_playing(mut next: ResMut<NextState<TestState>>) {
+    next.set(TestState::Playing);
+}
+fn play() {}
 
 struct ShortenTest1;
 impl ShortenTest1 {
@@ -33,21 +72,54 @@ impl ShortenTest2 {
 
 fn main() {
     let mut app = App::new();
-    app.add_plugins((DefaultPlugins, ScheduleGraphPlugin))
-        .add_systems(
-            Update,
+    app.add_plugins((
+        DefaultPlugins.set(LogPlugin {
+            update_subscriber: Some(timing_layer),
+            ..default()
+        }),
+        ScheduleGraphPlugin::default(),
+        StateGraphPlugin::<TestState>::default(),
+    ))
+    .init_state::<TestState>()
+    .add_systems(OnEnter(TestState::Menu), start_playing)
+    .add_systems(OnEnter(TestState::Playing), play)
+    .add_systems(Update, play.run_if(in_state(TestState::Playing)))
+    // Nothing is in TestSet2, it's only there to be ordered
+    .configure_sets(Update, TestSet2.after(TestSet))
+    .add_systems(
+        Update,
+        (
             (
-                (
-                    test1.before(test2).before(TestSet),
-                    test3.after(test1).in_set(TestSet),
-                    test2.after(test3).in_set(TestSet),
-                )
-                    .chain(),
-                test4.after(test3),
-                // ShortenTest1::test,
-                // ShortenTest2::test,
-            ),
-        );
+                test1.before(test2).before(TestSet),
+                test3.after(test1).in_set(TestSet),
+                test2.after(test3).in_set(TestSet),
+            )
+                .chain(),
+            test4.after(test3),
+            ShortenTest1::test,
+            ShortenTest2::test,
+            produce.pipe(consume),
+            || {},
+            || {},
+        ),
+    )
+    .annotate_system(
+        test4,
+        GraphAnnotation {
+            note: Some("Only needs test3's output, so it can run alongside the set".into()),
+            color: Some(Color::rgb(0.35, 0.45, 0.75)),
+            tags: vec!["tests".into()],
+            ..default()
+        },
+    )
+    .annotate_set(
+        TestSet,
+        GraphAnnotation {
+            label: Some("Test set".into()),
+            tags: vec!["tests".into()],
+            ..default()
+        },
+    );
     //     .add_systems(
     //         Update,
     //         (
@@ -56,5 +128,18 @@ fn main() {
     //             test3.in_set(TestSet2).in_set(TestSet),
     //         ),
     //     );
+
+    // Headless CI mode, check the schedules against the baseline and never start the app
+    if let Ok(baseline) = std::env::var(CHECK_ENV_VAR) {
+        while app.plugins_state() == PluginsState::Adding {
+            bevy::tasks::tick_global_task_pools_on_main_thread();
+        }
+        app.finish();
+        app.cleanup();
+        let bless = std::env::var_os(BLESS_ENV_VAR).is_some();
+        let report = run_schedule_check(&mut app, baseline.as_ref(), bless);
+        report.print();
+        std::process::exit(report.exit_code);
+    }
     app.run();
 }
@@ -0,0 +1,280 @@
+use bevy::{
+    ecs::schedule::{NodeId, ScheduleLabel},
+    prelude::*,
+    utils::petgraph::visit::{Dfs, Reversed},
+};
+
+use crate::{
+    graph_asserts::{ScheduleOrdering, SystemSelector},
+    graph_utils::{flatten_dependencies, set_systems, shortest_path},
+    schedule_graph::ScheduleInfo,
+};
+
+/// Where a single ordering constraint between two systems came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConstraintSource {
+    /// An edge straight between the two systems. Bevy only adds these for `.chain()`, but that
+    /// can't be told apart from anything else that might order them directly
+    Direct,
+    /// `.before(system)` and `.after(system)` go through the other system's type set
+    BeforeAfter { before: NodeId, after: NodeId },
+    /// The constraint is on a set that one (or both) of the systems is in
+    Set { before: NodeId, after: NodeId },
+}
+
+#[derive(Debug, Clone)]
+pub struct OrderingHop {
+    pub from: NodeId,
+    pub to: NodeId,
+    pub source: ConstraintSource,
+}
+
+/// The shortest chain of constraints that makes one node run before another
+#[derive(Debug, Clone, Default)]
+pub struct OrderingExplanation {
+    pub hops: Vec<OrderingHop>,
+}
+
+impl OrderingExplanation {
+    /// Every system along the way, in order
+    pub fn path(&self) -> Vec<NodeId> {
+        let mut path: Vec<_> = self.hops.iter().map(|h| h.from).collect();
+        path.extend(self.hops.last().map(|h| h.to));
+        path
+    }
+
+    pub fn describe(&self, info: &ScheduleInfo) -> String {
+        let path = self
+            .path()
+            .iter()
+            .map(|&n| info.name(n))
+            .collect::<Vec<_>>()
+            .join(" -> ");
+
+        let mut description = path;
+        for hop in &self.hops {
+            let (from, to) = (info.name(hop.from), info.name(hop.to));
+            description.push_str(&match hop.source {
+                ConstraintSource::Direct => {
+                    format!("\n  {from} -> {to}: ordered directly, like in a .chain()")
+                }
+                ConstraintSource::BeforeAfter { after, .. } => {
+                    if info.system_type_sets.contains(&after) {
+                        format!("\n  {from} -> {to}: {from}.before({to})")
+                    } else {
+                        format!("\n  {from} -> {to}: {to}.after({from})")
+                    }
+                }
+                ConstraintSource::Set { before, after } => {
+                    format!(
+                        "\n  {from} -> {to}: inherited from {} before {}",
+                        info.name(before),
+                        info.name(after)
+                    )
+                }
+            });
+        }
+        description
+    }
+}
+
+// The node itself followed by every set it is (transitively) in
+fn with_ancestors(info: &ScheduleInfo, node: NodeId) -> Vec<NodeId> {
+    if !info.hierarchy.contains_node(node) {
+        return vec![node];
+    }
+
+    let reversed = Reversed(&info.hierarchy);
+    let mut ancestors = Vec::new();
+    let mut dfs = Dfs::new(reversed, node);
+    while let Some(ancestor) = dfs.next(reversed) {
+        ancestors.push(ancestor);
+    }
+    ancestors
+}
+
+fn constraint_source(info: &ScheduleInfo, from: NodeId, to: NodeId) -> Option<ConstraintSource> {
+    let from_nodes = with_ancestors(info, from);
+    let to_nodes = with_ancestors(info, to);
+    // A system's only type set is its own
+    let is_own =
+        |node: NodeId, system: NodeId| node == system || info.system_type_sets.contains(&node);
+
+    let mut best = None;
+    for &before in &from_nodes {
+        for &after in &to_nodes {
+            if !info.dependencies.contains_edge(before, after) {
+                continue;
+            }
+            // Prefer the most direct explanation
+            let source = match (is_own(before, from), is_own(after, to)) {
+                _ if before == from && after == to => return Some(ConstraintSource::Direct),
+                (true, true) => ConstraintSource::BeforeAfter { before, after },
+                _ => ConstraintSource::Set { before, after },
+            };
+            if best.is_none() || matches!(source, ConstraintSource::BeforeAfter { .. }) {
+                best = Some(source);
+            }
+        }
+    }
+    best
+}
+
+/// Explains why `before` runs before `after`, either of which can be a system or a set.
+///
+/// Returns `None` if nothing orders them that way round.
+pub fn explain_ordering(
+    info: &ScheduleInfo,
+    before: NodeId,
+    after: NodeId,
+) -> Option<OrderingExplanation> {
+    explain_systems_ordering(
+        info,
+        &set_systems(&info.hierarchy, before),
+        &set_systems(&info.hierarchy, after),
+    )
+}
+
+/// Explains why some system in `before` runs before some system in `after`, for nodes bevy doesn't
+/// know about, like a crate's systems drawn together
+pub fn explain_systems_ordering(
+    info: &ScheduleInfo,
+    before: &[NodeId],
+    after: &[NodeId],
+) -> Option<OrderingExplanation> {
+    let flattened = flatten_dependencies(&info.dependencies, &info.hierarchy);
+    // Systems in both aren't ordered relative to each other
+    let targets: Vec<_> = after
+        .iter()
+        .copied()
+        .filter(|t| !before.contains(t))
+        .collect();
+    let path = shortest_path(&flattened, before, &targets)?;
+
+    let hops = path
+        .windows(2)
+        .map(|pair| {
+            Some(OrderingHop {
+                from: pair[0],
+                to: pair[1],
+                source: constraint_source(info, pair[0], pair[1])?,
+            })
+        })
+        .collect::<Option<_>>()?;
+
+    Some(OrderingExplanation { hops })
+}
+
+/// Describes why `a` runs before `b` in `schedule`, or why it doesn't
+pub fn explain_runs_before<M1, M2>(
+    app: &mut App,
+    schedule: impl ScheduleLabel,
+    a: impl SystemSelector<M1>,
+    b: impl SystemSelector<M2>,
+) -> String {
+    let ordering = ScheduleOrdering::new(app, schedule);
+    let (a, b) = (
+        ordering.resolve(&a.into_matcher()),
+        ordering.resolve(&b.into_matcher()),
+    );
+    let info = &ordering.info;
+
+    if let Some(explanation) = explain_ordering(info, a, b) {
+        explanation.describe(info)
+    } else if let Some(explanation) = explain_ordering(info, b, a) {
+        format!(
+            "{} runs after {}: {}",
+            info.name(a),
+            info.name(b),
+            explanation.describe(info)
+        )
+    } else {
+        format!("Nothing orders {} and {}", info.name(a), info.name(b))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+    enum Stage {
+        Early,
+        Late,
+    }
+
+    fn first() {}
+    fn second() {}
+    fn third() {}
+
+    fn explain(app: &mut App) -> String {
+        explain_runs_before(app, Update, first, second)
+    }
+
+    #[test]
+    fn chained() {
+        let mut app = App::new();
+        app.add_systems(Update, (first, second).chain());
+        assert_eq!(
+            explain(&mut app),
+            "first -> second\n  first -> second: ordered directly, like in a .chain()"
+        );
+    }
+
+    #[test]
+    fn before_and_after() {
+        let mut app = App::new();
+        app.add_systems(Update, (first.before(second), second));
+        assert_eq!(
+            explain(&mut app),
+            "first -> second\n  first -> second: first.before(second)"
+        );
+
+        let mut app = App::new();
+        app.add_systems(Update, (first, second.after(first)));
+        assert_eq!(
+            explain(&mut app),
+            "first -> second\n  first -> second: second.after(first)"
+        );
+    }
+
+    #[test]
+    fn inherited_from_sets() {
+        let mut app = App::new();
+        app.configure_sets(Update, Stage::Early.before(Stage::Late))
+            .add_systems(
+                Update,
+                (
+                    (first, third).in_set(Stage::Early),
+                    second.in_set(Stage::Late),
+                ),
+            );
+        assert_eq!(
+            explain(&mut app),
+            "first -> second\n  first -> second: inherited from Early before Late"
+        );
+    }
+
+    #[test]
+    fn through_other_systems() {
+        let mut app = App::new();
+        app.add_systems(Update, (first, third.after(first), second.after(third)));
+        assert_eq!(
+            explain(&mut app),
+            "first -> third -> second\n  first -> third: third.after(first)\n  \
+            third -> second: second.after(third)"
+        );
+        assert_eq!(
+            explain_runs_before(&mut app, Update, second, first),
+            "second runs after first: first -> third -> second\n  \
+            first -> third: third.after(first)\n  third -> second: second.after(third)"
+        );
+    }
+
+    #[test]
+    fn unordered() {
+        let mut app = App::new();
+        app.add_systems(Update, (first, second));
+        assert_eq!(explain(&mut app), "Nothing orders first and second");
+    }
+}
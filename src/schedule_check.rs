@@ -0,0 +1,417 @@
+use std::{fmt, fs, io, path::Path};
+
+use bevy::{
+    ecs::schedule::ScheduleBuildError,
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+use crate::{
+    annotations::{GraphAnnotation, GraphAnnotations},
+    graph_utils::{find_cycles, flatten_dependencies},
+    schedule_graph::{node_full_name, prebuild_schedule},
+};
+
+/// Set this to the path of a baseline file to check every schedule and exit instead of running
+/// (the app's `main` does this with `run_schedule_check`)
+pub const CHECK_ENV_VAR: &str = "BEVY_SYSTEM_GRAPH_CHECK";
+/// Set this alongside `BEVY_SYSTEM_GRAPH_CHECK` to overwrite the baseline with the current issues
+pub const BLESS_ENV_VAR: &str = "BEVY_SYSTEM_GRAPH_BLESS";
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ScheduleIssue {
+    /// Two systems with conflicting access and no ordering between them
+    Ambiguity {
+        schedule: String,
+        first: String,
+        second: String,
+    },
+    /// Nodes that (transitively) have to run before themselves
+    Cycle {
+        schedule: String,
+        nodes: Vec<String>,
+    },
+    /// Anything else `build_schedule` refused
+    BuildError { schedule: String, error: String },
+}
+
+impl ScheduleIssue {
+    /// The full names of the systems and sets it's about
+    pub fn nodes(&self) -> Vec<&str> {
+        match self {
+            ScheduleIssue::Ambiguity { first, second, .. } => vec![first, second],
+            ScheduleIssue::Cycle { nodes, .. } => nodes.iter().map(String::as_str).collect(),
+            ScheduleIssue::BuildError { .. } => Vec::new(),
+        }
+    }
+}
+
+// This is also the baseline file format, one issue per line
+impl fmt::Display for ScheduleIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduleIssue::Ambiguity {
+                schedule,
+                first,
+                second,
+            } => write!(f, "ambiguity\t{schedule}\t{first}\t{second}"),
+            ScheduleIssue::Cycle { schedule, nodes } => {
+                write!(f, "cycle\t{schedule}\t{}", nodes.join("\t"))
+            }
+            ScheduleIssue::BuildError { schedule, error } => {
+                // Keep it on one line
+                let error = error.split_whitespace().collect::<Vec<_>>().join(" ");
+                write!(f, "error\t{schedule}\t{error}")
+            }
+        }
+    }
+}
+
+/// Builds every schedule in the app and collects anything that looks like a scheduling mistake,
+/// with what the app said about the systems and sets in them by full name
+pub fn check_schedules(app: &mut App) -> (Vec<ScheduleIssue>, HashMap<String, GraphAnnotation>) {
+    let annotations = app
+        .world
+        .get_resource::<GraphAnnotations>()
+        .cloned()
+        .unwrap_or_default();
+    let mut annotated = HashMap::new();
+    let mut issues = app
+        .world
+        .resource_scope::<Schedules, _>(|world, mut schedules| {
+            let ignored_ambiguities = schedules.ignored_scheduling_ambiguities.clone();
+
+            let mut issues = Vec::new();
+            for (label, schedule) in schedules.iter_mut() {
+                let schedule_name = format!("{:?}", label);
+                let graph = schedule.graph();
+                for (node, annotation) in annotations.nodes(graph) {
+                    annotated.insert(node_full_name(graph, node), annotation.clone());
+                }
+
+                // Most cycles only show up once set dependencies are applied to their systems
+                let flattened =
+                    flatten_dependencies(graph.dependency().graph(), graph.hierarchy().graph());
+                let mut cycles = find_cycles(graph.dependency().graph());
+                cycles.extend(find_cycles(&flattened));
+                cycles.extend(find_cycles(graph.hierarchy().graph()));
+                for cycle in &cycles {
+                    let mut nodes: Vec<_> =
+                        cycle.iter().map(|&n| node_full_name(graph, n)).collect();
+                    nodes.sort();
+                    issues.push(ScheduleIssue::Cycle {
+                        schedule: schedule_name.clone(),
+                        nodes,
+                    });
+                }
+
+                match prebuild_schedule(schedule, world, &ignored_ambiguities) {
+                    Ok(()) => {
+                        let graph = schedule.graph();
+                        for (a, b, _) in graph.conflicting_systems() {
+                            let mut pair = [node_full_name(graph, *a), node_full_name(graph, *b)];
+                            pair.sort();
+                            let [first, second] = pair;
+                            issues.push(ScheduleIssue::Ambiguity {
+                                schedule: schedule_name.clone(),
+                                first,
+                                second,
+                            });
+                        }
+                    }
+                    // Already reported with better names
+                    Err(
+                        ScheduleBuildError::DependencyCycle(_)
+                        | ScheduleBuildError::HierarchyCycle(_),
+                    ) if !cycles.is_empty() => {}
+                    Err(e) => issues.push(ScheduleIssue::BuildError {
+                        schedule: schedule_name.clone(),
+                        error: e.to_string(),
+                    }),
+                }
+            }
+            issues
+        });
+
+    issues.sort();
+    issues.dedup();
+    (issues, annotated)
+}
+
+/// What the app said about the nodes in an issue, one line each
+fn annotation_lines(
+    issue: &ScheduleIssue,
+    annotations: &HashMap<String, GraphAnnotation>,
+) -> Vec<String> {
+    issue
+        .nodes()
+        .into_iter()
+        .filter_map(|node| Some(format!("{node}: {}", annotations.get(node)?)))
+        .collect()
+}
+
+/// What a schedule check found, and how the process should exit because of it
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ScheduleCheckReport {
+    /// For stdout: issues that have gone, and the summary if it passed
+    pub output: Vec<String>,
+    /// For stderr: new issues with what the app said about them, and why it failed
+    pub errors: Vec<String>,
+    /// 0 if it passed
+    pub exit_code: i32,
+}
+
+impl ScheduleCheckReport {
+    pub fn print(&self) {
+        for line in &self.output {
+            println!("{line}");
+        }
+        for line in &self.errors {
+            eprintln!("{line}");
+        }
+    }
+
+    fn failed(error: String) -> Self {
+        ScheduleCheckReport {
+            errors: vec![error],
+            exit_code: 1,
+            ..default()
+        }
+    }
+}
+
+/// The issues in a baseline file, ignoring blank lines and comments
+fn parse_baseline(contents: &str) -> HashSet<String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(String::from)
+        .collect()
+}
+
+fn read_baseline(path: &Path) -> io::Result<HashSet<String>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(parse_baseline(&contents)),
+        // No baseline yet means everything is new
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(HashSet::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// A baseline accepting all of these issues. Annotations go in as comments, so changing a note
+/// doesn't change what's checked
+fn bless_baseline(
+    issues: &[ScheduleIssue],
+    annotations: &HashMap<String, GraphAnnotation>,
+) -> String {
+    let mut contents = String::new();
+    for issue in issues {
+        contents.push_str(&issue.to_string());
+        contents.push('\n');
+        for annotation in annotation_lines(issue, annotations) {
+            contents.push_str(&format!("#   {annotation}\n"));
+        }
+    }
+    contents
+}
+
+/// Fails if there are any issues the baseline doesn't know about, and points out the ones it
+/// knows about that have gone
+fn compare_with_baseline(
+    issues: &[ScheduleIssue],
+    annotations: &HashMap<String, GraphAnnotation>,
+    known: &HashSet<String>,
+) -> ScheduleCheckReport {
+    let lines: Vec<_> = issues.iter().map(|i| i.to_string()).collect();
+    let new_issues: Vec<_> = issues
+        .iter()
+        .zip(&lines)
+        .filter(|(_, l)| !known.contains(*l))
+        .collect();
+    let current: HashSet<_> = lines.iter().cloned().collect();
+    let mut fixed: Vec<_> = known.difference(&current).collect();
+    fixed.sort();
+
+    let mut report = ScheduleCheckReport::default();
+    for line in &fixed {
+        report.output.push(format!(
+            "No longer present (remove from the baseline): {line}"
+        ));
+    }
+    for (issue, line) in &new_issues {
+        report.errors.push(format!("New schedule issue: {line}"));
+        for annotation in annotation_lines(issue, annotations) {
+            report.errors.push(format!("    {annotation}"));
+        }
+    }
+
+    if new_issues.is_empty() {
+        report.output.push(format!(
+            "Schedule check passed ({} known issues)",
+            lines.len()
+        ));
+    } else {
+        report.errors.push(format!(
+            "Schedule check failed with {} new issues, set {BLESS_ENV_VAR}=1 to accept them",
+            new_issues.len()
+        ));
+        report.exit_code = 1;
+    }
+    report
+}
+
+/// Compares the app's schedules against the baseline, or overwrites the baseline with them if
+/// `bless`. Meant for an app that has finished adding its plugins but isn't running, see
+/// `CHECK_ENV_VAR`
+pub fn run_schedule_check(app: &mut App, baseline: &Path, bless: bool) -> ScheduleCheckReport {
+    let (issues, annotations) = check_schedules(app);
+
+    if bless {
+        return match fs::write(baseline, bless_baseline(&issues, &annotations)) {
+            Ok(()) => ScheduleCheckReport {
+                output: vec![format!(
+                    "Wrote {} issues to {}",
+                    issues.len(),
+                    baseline.display()
+                )],
+                ..default()
+            },
+            Err(e) => ScheduleCheckReport::failed(format!(
+                "Failed to write baseline {}: {e}",
+                baseline.display()
+            )),
+        };
+    }
+
+    match read_baseline(baseline) {
+        Ok(known) => compare_with_baseline(&issues, &annotations, &known),
+        Err(e) => ScheduleCheckReport::failed(format!(
+            "Failed to read baseline {}: {e}",
+            baseline.display()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ambiguity(first: &str, second: &str) -> ScheduleIssue {
+        ScheduleIssue::Ambiguity {
+            schedule: "Update".to_string(),
+            first: first.to_string(),
+            second: second.to_string(),
+        }
+    }
+
+    fn annotated(node: &str, note: &str) -> HashMap<String, GraphAnnotation> {
+        let annotation = GraphAnnotation {
+            note: Some(note.to_string()),
+            ..default()
+        };
+        [(node.to_string(), annotation)].into_iter().collect()
+    }
+
+    #[test]
+    fn parse() {
+        let known = parse_baseline(
+            "# Known issues\n\nambiguity\tUpdate\ta\tb\n#   a: note \"why\"\n  cycle\tUpdate\tc\td  \n",
+        );
+        let mut known: Vec<_> = known.into_iter().collect();
+        known.sort();
+        assert_eq!(known, ["ambiguity\tUpdate\ta\tb", "cycle\tUpdate\tc\td"]);
+    }
+
+    #[test]
+    fn bless() {
+        let issues = [ambiguity("a", "b"), ambiguity("c", "d")];
+        let contents = bless_baseline(&issues, &annotated("a", "why"));
+        assert_eq!(
+            contents,
+            "ambiguity\tUpdate\ta\tb\n#   a: note \"why\"\nambiguity\tUpdate\tc\td\n"
+        );
+        // The annotations don't count as issues
+        let expected: HashSet<_> = issues.iter().map(|i| i.to_string()).collect();
+        assert_eq!(parse_baseline(&contents), expected);
+    }
+
+    #[test]
+    fn known_issues_pass() {
+        let issues = [ambiguity("a", "b")];
+        let known = parse_baseline(&bless_baseline(&issues, &HashMap::new()));
+        let report = compare_with_baseline(&issues, &HashMap::new(), &known);
+        assert_eq!(report.exit_code, 0);
+        assert_eq!(report.output, ["Schedule check passed (1 known issues)"]);
+        assert!(report.errors.is_empty());
+    }
+
+    #[test]
+    fn new_issues_fail() {
+        let known = parse_baseline("ambiguity\tUpdate\ta\tb\n");
+        let issues = [ambiguity("a", "b"), ambiguity("c", "d")];
+        let report = compare_with_baseline(&issues, &annotated("c", "why"), &known);
+        assert_eq!(report.exit_code, 1);
+        assert_eq!(
+            report.errors,
+            [
+                "New schedule issue: ambiguity\tUpdate\tc\td",
+                "    c: note \"why\"",
+                "Schedule check failed with 1 new issues, set BEVY_SYSTEM_GRAPH_BLESS=1 to accept \
+                them",
+            ]
+        );
+    }
+
+    #[test]
+    fn fixed_issues_pass() {
+        let known = parse_baseline("ambiguity\tUpdate\ta\tb\nambiguity\tUpdate\tc\td\n");
+        let report = compare_with_baseline(&[ambiguity("a", "b")], &HashMap::new(), &known);
+        assert_eq!(report.exit_code, 0);
+        assert_eq!(
+            report.output,
+            [
+                "No longer present (remove from the baseline): ambiguity\tUpdate\tc\td",
+                "Schedule check passed (1 known issues)",
+            ]
+        );
+    }
+
+    #[derive(Resource, Default)]
+    struct Counter(u32);
+
+    fn increment(mut counter: ResMut<Counter>) {
+        counter.0 += 1;
+    }
+
+    fn reset(mut counter: ResMut<Counter>) {
+        counter.0 = 0;
+    }
+
+    fn ambiguous_app() -> App {
+        let mut app = App::new();
+        app.init_resource::<Counter>()
+            .add_systems(Update, (increment, reset));
+        app
+    }
+
+    #[test]
+    fn check_then_bless() {
+        let baseline = std::env::temp_dir().join(format!(
+            "schedule_check_then_bless_{}.txt",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&baseline);
+
+        // No baseline yet, so the ambiguity is new
+        let report = run_schedule_check(&mut ambiguous_app(), &baseline, false);
+        assert_eq!(report.exit_code, 1);
+        assert!(report.errors[0].starts_with("New schedule issue: ambiguity\tUpdate\t"));
+
+        let report = run_schedule_check(&mut ambiguous_app(), &baseline, true);
+        assert_eq!(report.exit_code, 0);
+        let report = run_schedule_check(&mut ambiguous_app(), &baseline, false);
+        assert_eq!(report.exit_code, 0, "{:?}", report);
+        let _ = fs::remove_file(&baseline);
+    }
+}
@@ -1,25 +1,241 @@
+use std::{borrow::Cow, collections::BTreeSet};
+
 use bevy::{
-    ecs::schedule::{NodeId, ScheduleLabel},
+    app::{AppLabel, InternedAppLabel},
+    ecs::{
+        component::ComponentId,
+        schedule::{
+            InternedScheduleLabel, NodeId, ScheduleBuildError, ScheduleGraph, ScheduleLabel,
+        },
+    },
     prelude::*,
+    render::{view::RenderLayers, RenderApp},
     utils::{
-        petgraph::{self, graphmap::DiGraphMap},
-        HashMap,
+        petgraph::{self, graphmap::DiGraphMap, visit::Dfs},
+        HashMap, HashSet,
     },
 };
 
 use crate::{
-    graph_ui::setup,
+    annotations::{
+        apply_annotations, cycle_tag_filter, filter_tagged_nodes, GraphAnnotation,
+        GraphAnnotations, TagFilter,
+    },
+    chains::{chain_controls, compress_chains, expand_chains, ChainCompression},
+    crate_groups::{
+        crate_grouping_controls, expand_crate_groups, group_by_crate, CrateGroup, CrateGrouping,
+    },
+    executor_sim::{
+        halve_selected_costs, show_simulation, simulation_controls, SimulationSettings,
+    },
+    frame_runs::{show_frame_runs, track_frame_runs, FrameRuns},
+    gantt::{capture_frame, draw_gantt, select_gantt_bar, CapturedFrame},
+    graph_refresh::{
+        refresh_controls, refresh_schedule_graph, RefreshScheduleGraph, ScheduleSignature,
+        ShowSchedule,
+    },
+    graph_stats::{update_measured_costs, update_schedule_stats, ScheduleStats, SystemCosts},
+    graph_stepping::{add_stepping_schedule, show_stepping, stepping_controls, GraphStepping},
+    graph_ui::{
+        colour_nodes, draw_graph, draw_set_edges, explain_selection, graph_window_focused,
+        highlight_critical_path, select_nodes, setup, show_full_name, show_stats,
+        show_system_timings, toggle_critical_path, CriticalPath, HighlightedPath, NodeSelection,
+        NodeTimings, SetEdgeGizmos, GRAPH_RENDER_LAYER,
+    },
     graph_utils::*,
-    layer_graph::{LayerGraph, LayerNode},
-    shorten_type::shorten_systems,
+    layer_graph::{DrawnNode, LayerGraph, LayerNode},
+    schedule_check::CHECK_ENV_VAR,
+    schedule_order::{
+        draw_schedule_order, select_schedule, toggle_schedule_order, update_schedule_order,
+        ScheduleOrderView,
+    },
+    set_display::{set_display_controls, SetDisplay},
+    shorten_type::{
+        cycle_name_style, name_anonymous_sets, shorten_systems, system_type_set_name, NameStyle,
+        SystemName,
+    },
+    states_view::{
+        draw_states_view, filter_state_nodes, find_state_types, select_state_filter,
+        toggle_states_view, StatesView,
+    },
+    sub_apps::add_sub_app_snapshots,
+    system_timing::{
+        collect_system_timings, freeze_system_timings, RecentSystemRuns, SystemTimings,
+        TimingSettings,
+    },
 };
 
-pub struct ScheduleGraphPlugin;
+pub struct ScheduleGraphPlugin {
+    /// Sub-apps whose schedules can be graphed too, if the app has them
+    pub sub_apps: Vec<InternedAppLabel>,
+    /// How systems are labelled to start with
+    pub name_style: NameStyle,
+    /// Which sets are drawn to start with
+    pub sets: SetDisplay,
+}
+
+impl Default for ScheduleGraphPlugin {
+    fn default() -> Self {
+        ScheduleGraphPlugin {
+            sub_apps: vec![RenderApp.intern()],
+            name_style: NameStyle::default(),
+            sets: SetDisplay::default(),
+        }
+    }
+}
 
 impl Plugin for ScheduleGraphPlugin {
     fn finish(&self, app: &mut App) {
-        let layer_graph = build_schedule_graph(In(PostUpdate), &mut app.world);
-        app.insert_resource(layer_graph).add_systems(Startup, setup);
+        // Headless CI mode, where the app is only built to check its schedules. The graph's own
+        // systems would just get in the way
+        if std::env::var_os(CHECK_ENV_VAR).is_some() {
+            return;
+        }
+
+        app.insert_resource(self.name_style)
+            .init_resource::<CrateGrouping>()
+            .init_resource::<ChainCompression>()
+            .insert_resource(self.sets.clone())
+            .insert_gizmo_group(
+                SetEdgeGizmos,
+                GizmoConfig {
+                    render_layers: RenderLayers::layer(GRAPH_RENDER_LAYER),
+                    ..default()
+                },
+            )
+            .init_resource::<GraphAnnotations>()
+            .init_resource::<TagFilter>();
+        // Only the run conditions for now, the rest once bevy has built the schedule
+        let (layer_graph, schedule_info) = build_schedule_graph(In(PostUpdate), &app.world)
+            .unwrap_or_else(|e| {
+                error!("Couldn't lay out {:?}, showing it empty: {e}", PostUpdate);
+                schedule_graph_layout(&Schedule::new(PostUpdate), &LayoutSettings::default())
+                    .expect("an empty schedule has no cycles")
+            });
+        app.insert_resource(layer_graph)
+            .insert_resource(schedule_info)
+            .init_resource::<ScheduleSignature>()
+            .add_event::<RefreshScheduleGraph>()
+            .init_resource::<NodeSelection>()
+            .init_resource::<HighlightedPath>()
+            .init_resource::<CriticalPath>()
+            .init_resource::<SystemCosts>()
+            .init_resource::<ScheduleStats>()
+            .init_resource::<TimingSettings>()
+            .init_resource::<RecentSystemRuns>()
+            .init_resource::<SystemTimings>()
+            .init_resource::<NodeTimings>()
+            .init_resource::<CapturedFrame>()
+            .init_resource::<SimulationSettings>()
+            .init_resource::<FrameRuns>()
+            .init_resource::<GraphStepping>()
+            .add_systems(Startup, setup)
+            .add_event::<ShowSchedule>()
+            .init_resource::<ScheduleOrderView>()
+            // In Main so that any of the schedules it runs can be graphed (they're taken out of
+            // `Schedules` while running)
+            .add_systems(
+                Main,
+                (
+                    refresh_schedule_graph,
+                    collect_system_timings,
+                    track_frame_runs,
+                    update_schedule_order,
+                    find_state_types,
+                )
+                    .chain()
+                    .before(Main::run_main),
+            )
+            .add_systems(
+                Update,
+                (refresh_controls, freeze_system_timings).run_if(graph_window_focused),
+            )
+            .add_systems(
+                Update,
+                (
+                    draw_graph,
+                    (
+                        select_nodes,
+                        select_gantt_bar,
+                        stepping_controls.run_if(graph_window_focused),
+                    ),
+                    explain_selection,
+                    (
+                        show_system_timings,
+                        update_measured_costs,
+                        show_frame_runs,
+                        show_stepping,
+                    ),
+                    update_schedule_stats,
+                    (
+                        show_stats,
+                        (
+                            toggle_critical_path.run_if(graph_window_focused),
+                            highlight_critical_path,
+                        )
+                            .chain(),
+                    ),
+                    colour_nodes,
+                )
+                    .chain(),
+            )
+            .add_systems(
+                Update,
+                (
+                    (capture_frame, simulation_controls, halve_selected_costs)
+                        .run_if(graph_window_focused),
+                    show_simulation,
+                    draw_gantt,
+                )
+                    .chain(),
+            )
+            .add_systems(
+                Update,
+                (
+                    toggle_schedule_order.run_if(graph_window_focused),
+                    draw_schedule_order,
+                    select_schedule,
+                )
+                    .chain(),
+            )
+            .init_resource::<StatesView>()
+            .add_systems(
+                Update,
+                (
+                    (
+                        crate_grouping_controls.run_if(graph_window_focused),
+                        expand_crate_groups,
+                    )
+                        .after(select_nodes),
+                    (chain_controls.run_if(graph_window_focused), expand_chains)
+                        .after(select_nodes),
+                    (cycle_name_style, set_display_controls).run_if(graph_window_focused),
+                    show_full_name,
+                    draw_set_edges,
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    toggle_states_view.run_if(graph_window_focused),
+                    select_state_filter,
+                    filter_state_nodes,
+                    draw_states_view,
+                )
+                    .chain()
+                    .before(colour_nodes),
+            )
+            .add_systems(
+                Update,
+                (
+                    cycle_tag_filter.run_if(graph_window_focused),
+                    filter_tagged_nodes,
+                )
+                    .chain()
+                    .before(colour_nodes),
+            );
+        add_stepping_schedule(app);
+        add_sub_app_snapshots(app, &self.sub_apps);
     }
     fn build(&self, _app: &mut App) {}
 }
@@ -28,183 +244,477 @@ impl Plugin for ScheduleGraphPlugin {
 struct ScheduleDebugGroup;
 
 // TODO: Figure out how to run this as a 'normal' system
-fn build_schedule_graph<S: ScheduleLabel + Clone>(
+/// Lays out a schedule without touching it, so it is only complete once bevy has built it (the
+/// first time it runs). Until then only its run conditions are known, which bevy moves out of the
+/// graph when building it.
+pub fn build_schedule_graph<S: ScheduleLabel + Clone>(
     In(schedule_label): In<S>,
+    world: &World,
+) -> Result<(LayerGraph, ScheduleInfo), ScheduleBuildError> {
+    let settings = LayoutSettings::from_world(world);
+    match world.resource::<Schedules>().get(schedule_label.clone()) {
+        Some(schedule) if schedule.systems().is_ok() => schedule_graph_layout(schedule, &settings),
+        Some(schedule) => {
+            let (layer_graph, mut info) =
+                schedule_graph_layout(&Schedule::new(schedule_label), &settings)?;
+            info.conditions = schedule_conditions(schedule);
+            Ok((layer_graph, info))
+        }
+        // It might not have been made yet, show it empty until it is
+        None => schedule_graph_layout(&Schedule::new(schedule_label), &settings),
+    }
+}
+
+/// Fills in the dependency and conflict information of a schedule that bevy hasn't built yet.
+pub fn prebuild_schedule(
+    schedule: &mut Schedule,
     world: &mut World,
-) -> LayerGraph {
-    world.resource_scope::<Schedules, _>(|world, mut schedules| {
-        let ignored_ambiguities = schedules.ignored_scheduling_ambiguities.clone();
+    ignored_ambiguities: &BTreeSet<ComponentId>,
+) -> Result<(), ScheduleBuildError> {
+    // Bevy has already built it (and moved the systems out of the graph)
+    if schedule.systems().is_ok() {
+        return Ok(());
+    }
 
-        let schedule = schedules.get_mut(schedule_label).unwrap();
-        schedule.graph_mut().initialize(world);
-        let _ = schedule.graph_mut().build_schedule(
+    let graph = schedule.graph_mut();
+    graph.initialize(world);
+    graph
+        .build_schedule(
             world.components(),
             ScheduleDebugGroup.intern(),
-            &ignored_ambiguities,
-        );
+            ignored_ambiguities,
+        )
+        .map(|_| ())
+}
 
-        schedule_graph_layout(schedule)
-    })
+/// All the systems in a schedule, whether or not bevy has moved them into the executable schedule
+pub fn schedule_systems(schedule: &Schedule) -> Vec<(NodeId, &dyn System<In = (), Out = ()>)> {
+    match schedule.systems() {
+        Ok(systems) => systems.map(|(n, s)| (n, s.as_ref())).collect(),
+        Err(_) => schedule.graph().systems().map(|(n, s, _)| (n, s)).collect(),
+    }
 }
 
-fn schedule_graph_layout(schedule: &Schedule) -> LayerGraph {
+/// The full names of the run conditions on each system and set, while they're still in the graph
+pub fn schedule_conditions(schedule: &Schedule) -> HashMap<NodeId, Vec<String>> {
     let graph = schedule.graph();
-    let mut dependencies = graph.dependency().graph().clone();
-    let mut hierarchy = graph.hierarchy().graph().clone();
-    let topsort = graph.dependency().cached_topsort().to_vec();
-
-    // Remove all trivial sets
-    for node in topsort {
-        if node.is_set() {
-            let mut systems = hierarchy.neighbors_directed(node, petgraph::Direction::Outgoing);
-
-            // Remove trivial sets (ones with only one system)
-            if let Some(system_node) = systems.next() {
-                if systems.next().is_none() {
-                    let incoming_set_deps: Vec<_> = dependencies
-                        .neighbors_directed(node, petgraph::Direction::Incoming)
-                        .collect();
-                    let outgoing_set_deps: Vec<_> = dependencies
-                        .neighbors_directed(node, petgraph::Direction::Outgoing)
-                        .collect();
+    graph
+        .systems()
+        .map(|(n, _, c)| (n, c))
+        .chain(graph.system_sets().map(|(n, _, c)| (n, c)))
+        .filter(|(_, c)| !c.is_empty())
+        .map(|(n, c)| (n, c.iter().map(|c| c.name().to_string()).collect()))
+        .collect()
+}
 
-                    for incoming_node in incoming_set_deps {
-                        dependencies.add_edge(incoming_node, system_node, ());
-                    }
+pub fn node_full_name(graph: &ScheduleGraph, node: NodeId) -> String {
+    match node {
+        NodeId::System(_) => graph
+            .get_system_at(node)
+            .map(|s| s.name().to_string())
+            .unwrap_or_else(|| format!("{:?}", node)),
+        NodeId::Set(_) => format!("{:?}", graph.set_at(node)),
+    }
+}
+
+/// The parts of a schedule's graph that are still needed once it has been laid out
+#[derive(Debug, Clone, Resource)]
+pub struct ScheduleInfo {
+    /// The sub-app the schedule is in, `None` for the main app
+    pub app: Option<InternedAppLabel>,
+    pub schedule: InternedScheduleLabel,
+    pub label: String,
+    pub dependencies: DiGraphMap<NodeId, ()>,
+    pub hierarchy: DiGraphMap<NodeId, ()>,
+    /// Shortened names for display
+    pub names: HashMap<NodeId, String>,
+    pub full_names: HashMap<NodeId, String>,
+    /// The sets bevy creates for each system function, used by `.before(system)` and co
+    pub system_type_sets: HashSet<NodeId>,
+    /// The sets bevy creates to hold the systems of `(a, b).run_if(..)`
+    pub anonymous_sets: HashSet<NodeId>,
+    /// Pairs of systems whose data access stops them running at the same time, both ways round
+    pub conflicts: HashSet<(NodeId, NodeId)>,
+    /// Systems that need the whole world to themselves
+    pub exclusive_systems: HashSet<NodeId>,
+    /// Systems that have to run on the main thread
+    pub non_send_systems: HashSet<NodeId>,
+    /// The full names of the run conditions on each system and set, only known if bevy hadn't
+    /// built the schedule yet (it moves them out of the graph)
+    pub conditions: HashMap<NodeId, Vec<String>>,
+    /// How it was laid out and labelled
+    pub settings: LayoutSettings,
+    /// Crates whose systems are drawn as one node, by `DrawnNode::CrateGroup` index
+    pub crate_groups: Vec<CrateGroup>,
+    /// Runs of systems that just follow one another drawn as one node, by `DrawnNode::Chain` index
+    pub chains: Vec<Vec<NodeId>>,
+    /// The crate group or chain each system in one is drawn as
+    pub grouped_systems: HashMap<NodeId, DrawnNode>,
+    /// Systems made by piping or combining others
+    pub compound_systems: HashSet<NodeId>,
+    /// What the app said about its systems and sets, see `AnnotateGraphExt`
+    pub annotations: HashMap<NodeId, GraphAnnotation>,
+    /// Sets with no systems in them, drawn as placeholders
+    pub empty_sets: HashSet<NodeId>,
+    /// Sets drawn as just their systems, with the ordering that was moved from them onto the systems
+    pub collapsed_sets: HashMap<NodeId, Vec<(NodeId, NodeId)>>,
+}
+
+impl ScheduleInfo {
+    pub fn new(schedule: &Schedule) -> ScheduleInfo {
+        let graph = schedule.graph();
+        let systems = schedule_systems(schedule);
 
-                    for outgoing_node in outgoing_set_deps {
-                        dependencies.add_edge(system_node, outgoing_node, ());
-                    }
+        let full_names = systems
+            .iter()
+            .map(|(n, s)| (*n, s.name().to_string()))
+            .chain(graph.system_sets().map(|(n, s, _)| (n, format!("{:?}", s))))
+            .collect();
 
-                    dependencies.remove_node(node);
-                    hierarchy.remove_node(node);
+        let mut conflicts = HashSet::new();
+        for (i, (a, a_system)) in systems.iter().enumerate() {
+            for (b, b_system) in &systems[i + 1..] {
+                if !a_system
+                    .component_access()
+                    .is_compatible(b_system.component_access())
+                {
+                    conflicts.insert((*a, *b));
+                    conflicts.insert((*b, *a));
                 }
-            } else {
             }
         }
+
+        let mut info = ScheduleInfo {
+            app: None,
+            schedule: schedule.label(),
+            label: format!("{:?}", schedule.label()),
+            dependencies: graph.dependency().graph().clone(),
+            hierarchy: graph.hierarchy().graph().clone(),
+            names: HashMap::new(),
+            full_names,
+            system_type_sets: graph
+                .system_sets()
+                .filter(|(_, s, _)| s.system_type().is_some())
+                .map(|(n, _, _)| n)
+                .collect(),
+            anonymous_sets: graph
+                .system_sets()
+                .filter(|(_, s, _)| s.is_anonymous())
+                .map(|(n, _, _)| n)
+                .collect(),
+            conflicts,
+            exclusive_systems: systems
+                .iter()
+                .filter(|(_, s)| s.is_exclusive())
+                .map(|(n, _)| *n)
+                .collect(),
+            non_send_systems: systems
+                .iter()
+                .filter(|(_, s)| !s.is_send())
+                .map(|(n, _)| *n)
+                .collect(),
+            conditions: schedule_conditions(schedule),
+            settings: LayoutSettings::default(),
+            crate_groups: Vec::new(),
+            chains: Vec::new(),
+            grouped_systems: HashMap::new(),
+            compound_systems: systems
+                .iter()
+                .filter(|(_, s)| SystemName::parse(&s.name()).is_compound())
+                .map(|(n, _)| *n)
+                .collect(),
+            annotations: HashMap::new(),
+            empty_sets: HashSet::new(),
+            collapsed_sets: HashMap::new(),
+        };
+        info.restyle_names(NameStyle::default());
+        info
     }
 
-    let topsort = topsort_graph(&dependencies).unwrap();
-    // Simplify duplicates dependencies between systems and their sets
-    // for &node in &topsort {
-    //     if node.is_set() {
-    //         let systems: Vec<_> = hierarchy
-    //             .neighbors_directed(node, petgraph::Direction::Outgoing)
-    //             .collect();
-
-    //         let incoming_set_deps: HashSet<_> = dependencies
-    //             .neighbors_directed(node, petgraph::Direction::Incoming)
-    //             .collect();
-    //         let outgoing_set_deps: HashSet<_> = dependencies
-    //             .neighbors_directed(node, petgraph::Direction::Outgoing)
-    //             .collect();
-
-    //         // Remove dependencies on systems that also apply to their set
-    //         for system in systems {
-    //             let incoming_deps: HashSet<_> = dependencies
-    //                 .neighbors_directed(system, petgraph::Direction::Incoming)
-    //                 .collect();
-    //             let outgoing_deps: HashSet<_> = dependencies
-    //                 .neighbors_directed(system, petgraph::Direction::Outgoing)
-    //                 .collect();
-
-    //             for &shared in incoming_deps.intersection(&incoming_set_deps) {
-    //                 dependencies.remove_edge(shared, system);
-    //             }
-    //             for &shared in outgoing_deps.intersection(&outgoing_set_deps) {
-    //                 dependencies.remove_edge(system, shared);
-    //             }
-    //         }
-    //     }
-    // }
-
-    let graph_info = check_graph(&dependencies, &topsort);
-    let graph_map = graph_info.transitive_reduction; // Is this doing anything?
-
-    // This is just setless systems and sets with added constraints from all their systems so they layer correctly
-    let mut layering_graph = DiGraphMap::new(); // Is it more efficient to capacity this (it will overallocate)
-    for &node in &topsort {
-        // System without a set should be added
-        if node.is_set()
-            || (node.is_system()
-                && hierarchy
-                    .neighbors_directed(node, petgraph::Direction::Incoming)
-                    .next()
-                    .is_none())
-        {
-            layering_graph.add_node(node);
-            for child_node in dependencies.neighbors_directed(node, petgraph::Direction::Outgoing) {
-                if child_node.is_set()
-                    || (child_node.is_system()
-                        && hierarchy
-                            .neighbors_directed(child_node, petgraph::Direction::Incoming)
-                            .next()
-                            .is_none())
-                {
-                    layering_graph.add_edge(node, child_node, ());
-                } else {
-                    for set in
-                        hierarchy.neighbors_directed(child_node, petgraph::Direction::Incoming)
-                    {
-                        // Don't create mini-cycles
-                        if node != set {
-                            layering_graph.add_edge(node, set, ());
-                        }
-                    }
-                }
+    /// Labels the systems and sets in another style
+    pub fn restyle_names(&mut self, style: NameStyle) {
+        let (systems, sets): (HashMap<_, _>, HashMap<_, _>) = self
+            .full_names
+            .iter()
+            .map(|(&node, name)| (node, Cow::from(name.as_str())))
+            .partition(|(node, _)| node.is_system());
+        let mut names = shorten_systems(systems, style);
+
+        let (generated, named) = sets.into_iter().partition(|(node, _)| {
+            self.system_type_sets.contains(node) || self.anonymous_sets.contains(node)
+        });
+        names.extend(shorten_systems(named, style));
+        let generated: HashMap<_, _> = generated;
+        // Named after their function but not numbered like its systems, so it isn't mistaken for
+        // one of them
+        for &set in &self.system_type_sets {
+            let system = self
+                .hierarchy
+                .neighbors_directed(set, petgraph::Direction::Outgoing)
+                .find_map(|system| self.full_names.get(&system));
+            let name = match system {
+                Some(name) => shorten_systems([(set, Cow::from(name.as_str()))].into(), style)
+                    .remove(&set)
+                    .unwrap(),
+                None => system_type_set_name(&generated[&set]),
+            };
+            names.insert(set, format!("{} (type set)", name));
+        }
+        names.extend(name_anonymous_sets(
+            self.anonymous_sets
+                .iter()
+                .map(|&set| {
+                    let systems = set_systems(&self.hierarchy, set)
+                        .iter()
+                        .filter_map(|system| self.full_names.get(system))
+                        .map(String::as_str)
+                        .collect();
+                    (set, systems)
+                })
+                .collect(),
+        ));
+        self.names.extend(names);
+    }
+
+    pub fn name(&self, node: NodeId) -> &str {
+        self.names.get(&node).map_or("<unknown>", String::as_str)
+    }
+
+    pub fn drawn_name(&self, node: DrawnNode) -> String {
+        match node {
+            DrawnNode::Node(node) => self.name(node).to_string(),
+            DrawnNode::CrateGroup(i) => {
+                let group = &self.crate_groups[i];
+                format!("{} ({} systems)", group.name, group.systems.len())
+            }
+            DrawnNode::Chain(i) => {
+                let chain = &self.chains[i];
+                format!(
+                    "chain of {} systems ({} .. {})",
+                    chain.len(),
+                    self.name(chain[0]),
+                    self.name(chain[chain.len() - 1])
+                )
             }
         }
+    }
 
-        if node.is_set() {
-            for system in hierarchy.neighbors_directed(node, petgraph::Direction::Outgoing) {
-                // TODO: Dedup this
-                for child_node in
-                    dependencies.neighbors_directed(system, petgraph::Direction::Outgoing)
-                {
-                    if child_node.is_set()
-                        || (child_node.is_system()
-                            && hierarchy
-                                .neighbors_directed(child_node, petgraph::Direction::Incoming)
-                                .next()
-                                .is_none())
-                    {
-                        layering_graph.add_edge(node, child_node, ());
-                    } else {
-                        for set in
-                            hierarchy.neighbors_directed(child_node, petgraph::Direction::Incoming)
-                        {
-                            // Don't create mini-cycles
-                            if node != set {
-                                layering_graph.add_edge(node, set, ());
-                            }
-                        }
-                    }
-                }
+    pub fn drawn_full_name(&self, node: DrawnNode) -> String {
+        match node {
+            DrawnNode::Node(node) => self.full_names.get(&node).cloned().unwrap_or_default(),
+            DrawnNode::CrateGroup(i) => self.crate_groups[i].name.clone(),
+            DrawnNode::Chain(i) => {
+                let names: Vec<_> = self.chains[i].iter().map(|&s| self.name(s)).collect();
+                names.join(" -> ")
             }
         }
     }
-    let layer_topsort = topsort_graph(&layering_graph).unwrap();
 
-    let mut layers: HashMap<NodeId, usize> = HashMap::new();
-    for &node in &layer_topsort {
-        if node.is_set()
-            || (node.is_system()
-                && hierarchy
-                    .neighbors_directed(node, petgraph::Direction::Incoming)
-                    .next()
-                    .is_none())
-        {
-            let incoming_neighbours = layering_graph
-                .neighbors_directed(node, petgraph::Direction::Incoming)
-                .collect::<Vec<_>>();
+    /// Every system inside a drawn node
+    pub fn drawn_systems(&self, node: DrawnNode) -> Vec<NodeId> {
+        match node {
+            DrawnNode::Node(node) => set_systems(&self.hierarchy, node),
+            DrawnNode::CrateGroup(i) => self.crate_groups[i].systems.clone(),
+            DrawnNode::Chain(i) => self.chains[i].clone(),
+        }
+    }
+}
+
+/// What changes how a schedule is laid out and labelled, from the resources of the same types
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LayoutSettings {
+    pub grouping: CrateGrouping,
+    pub chains: ChainCompression,
+    pub name_style: NameStyle,
+    pub sets: SetDisplay,
+    pub annotations: GraphAnnotations,
+}
+
+impl LayoutSettings {
+    pub fn from_world(world: &World) -> Self {
+        LayoutSettings {
+            grouping: world
+                .get_resource::<CrateGrouping>()
+                .cloned()
+                .unwrap_or_default(),
+            chains: world
+                .get_resource::<ChainCompression>()
+                .cloned()
+                .unwrap_or_default(),
+            name_style: world
+                .get_resource::<NameStyle>()
+                .copied()
+                .unwrap_or_default(),
+            sets: world
+                .get_resource::<SetDisplay>()
+                .cloned()
+                .unwrap_or_default(),
+            annotations: world
+                .get_resource::<GraphAnnotations>()
+                .cloned()
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Whether any of the resources they come from changed since the system calling this last ran,
+    /// which is cheaper than comparing them (the annotations can be big)
+    pub fn changed(world: &World) -> bool {
+        world.is_resource_changed::<CrateGrouping>()
+            || world.is_resource_changed::<ChainCompression>()
+            || world.is_resource_changed::<NameStyle>()
+            || world.is_resource_changed::<SetDisplay>()
+            || world.is_resource_changed::<GraphAnnotations>()
+    }
+}
 
-            if incoming_neighbours.is_empty() {
-                layers.insert(node, 0);
+/// Lays a schedule out in layers. Fails if what's drawn would still have a cycle in it, with the
+/// names of the nodes in each cycle
+pub fn schedule_graph_layout(
+    schedule: &Schedule,
+    settings: &LayoutSettings,
+) -> Result<(LayerGraph, ScheduleInfo), ScheduleBuildError> {
+    let mut info = ScheduleInfo::new(schedule);
+    info.settings = settings.clone();
+    if settings.name_style != NameStyle::default() {
+        info.restyle_names(settings.name_style);
+    }
+    let graph = schedule.graph();
+    apply_annotations(graph, &mut info, &settings.annotations);
+    let mut dependencies = graph.dependency().graph().clone();
+    let mut hierarchy = graph.hierarchy().graph().clone();
+    // Collapse the sets the settings say to into their systems (however deeply nested). Their
+    // ordering goes onto the systems and whatever they're in now holds what they held
+    let sets: Vec<_> = hierarchy.nodes().filter(NodeId::is_set).collect();
+    for set in sets {
+        let systems = set_systems(&hierarchy, set);
+        if systems.is_empty() {
+            if settings.sets.hide_empty {
+                contract_node(&mut dependencies, set);
+                hierarchy.remove_node(set);
             } else {
-                let parent_layer = incoming_neighbours.iter().map(|n| layers[n]).max().unwrap();
-                layers.insert(node, parent_layer + 1);
+                info.empty_sets.insert(set);
+            }
+            continue;
+        }
+        if !settings.sets.collapses(&info, set, systems.len()) {
+            continue;
+        }
+
+        let incoming: Vec<_> = dependencies
+            .neighbors_directed(set, petgraph::Direction::Incoming)
+            .flat_map(|node| systems.iter().map(move |&system| (node, system)))
+            .collect();
+        let outgoing: Vec<_> = dependencies
+            .neighbors_directed(set, petgraph::Direction::Outgoing)
+            .flat_map(|node| systems.iter().map(move |&system| (system, node)))
+            .collect();
+        let moved: Vec<_> = incoming.into_iter().chain(outgoing).collect();
+        for &(a, b) in &moved {
+            dependencies.add_edge(a, b, ());
+        }
+        info.collapsed_sets.insert(set, moved);
+
+        let parents: Vec<_> = hierarchy
+            .neighbors_directed(set, petgraph::Direction::Incoming)
+            .collect();
+        let children: Vec<_> = hierarchy
+            .neighbors_directed(set, petgraph::Direction::Outgoing)
+            .collect();
+        for &parent in &parents {
+            for &child in &children {
+                hierarchy.add_edge(parent, child, ());
             }
         }
+        dependencies.remove_node(set);
+        hierarchy.remove_node(set);
+    }
+
+    // A system's own type set goes inside the sets all its systems are in, rather than overlapping
+    let type_sets: Vec<_> = info
+        .system_type_sets
+        .iter()
+        .copied()
+        .filter(|&set| hierarchy.contains_node(set))
+        .collect();
+    for type_set in type_sets {
+        let systems = set_systems(&hierarchy, type_set);
+        let mut parents: Vec<_> = systems
+            .iter()
+            .flat_map(|&system| hierarchy.neighbors_directed(system, petgraph::Direction::Incoming))
+            .filter(|&parent| parent != type_set && !info.system_type_sets.contains(&parent))
+            .collect();
+        parents.sort();
+        parents.dedup();
+        for parent in parents {
+            if systems
+                .iter()
+                .all(|&system| hierarchy.contains_edge(parent, system))
+            {
+                hierarchy.add_edge(parent, type_set, ());
+            }
+        }
+    }
+
+    group_by_crate(
+        &mut dependencies,
+        &mut hierarchy,
+        &mut info,
+        &settings.grouping,
+    );
+
+    let layer_graph = layout_level(&dependencies, &hierarchy, &mut info, Some(&settings.chains))?;
+    Ok((layer_graph, info))
+}
+
+/// Lays out one level of the graph, the whole schedule or what's inside a set drawn on it. Only
+/// the outermost sets of the level are drawn on it, each with everything in them laid out inside.
+/// A set that can't be drawn as one node without its ordering going both ways is dissolved into
+/// what it holds. Chains are only looked for if `chains` is given
+fn layout_level(
+    dependencies: &DiGraphMap<NodeId, ()>,
+    hierarchy: &DiGraphMap<NodeId, ()>,
+    info: &mut ScheduleInfo,
+    chains: Option<&ChainCompression>,
+) -> Result<LayerGraph, ScheduleBuildError> {
+    let mut dissolved = HashSet::new();
+    let mut layering_graph = loop {
+        let layering_graph = layering_graph(dependencies, hierarchy, info, &dissolved);
+        let mut cycle_sets: Vec<_> = find_cycles(&layering_graph)
+            .into_iter()
+            .filter_map(|cycle| {
+                cycle
+                    .into_iter()
+                    .filter_map(DrawnNode::node)
+                    .filter(|node| node.is_set() && !dissolved.contains(node))
+                    .max_by_key(|&set| (set_systems(hierarchy, set).len(), set))
+            })
+            .collect();
+        if cycle_sets.is_empty() {
+            let cycles = find_cycles(&layering_graph);
+            if !cycles.is_empty() {
+                let names: Vec<Vec<_>> = cycles
+                    .into_iter()
+                    .map(|cycle| cycle.into_iter().map(|n| info.drawn_name(n)).collect())
+                    .collect();
+                return Err(ScheduleBuildError::DependencyCycle(format!("{:?}", names)));
+            }
+            break layering_graph;
+        }
+        cycle_sets.sort();
+        dissolved.extend(cycle_sets);
+    };
+    if let Some(chains) = chains {
+        compress_chains(&mut layering_graph, info, chains)?;
+    }
+
+    let layer_topsort = topsort_graph(&layering_graph)?;
+    let mut layers: HashMap<DrawnNode, usize> = HashMap::new();
+    for &node in &layer_topsort {
+        let parent_layer = layering_graph
+            .neighbors_directed(node, petgraph::Direction::Incoming)
+            .map(|n| layers[&n] + 1)
+            .max();
+        layers.insert(node, parent_layer.unwrap_or(0));
     }
 
     let mut layers_vec = Vec::new();
@@ -215,27 +725,52 @@ fn schedule_graph_layout(schedule: &Schedule) -> LayerGraph {
         layers_vec[layer].push(node);
     }
 
-    let node_names = shorten_systems(graph.systems().map(|(n, s, _)| (n, s.name())).collect())
-        .into_iter()
-        .chain(graph.system_sets().map(|(n, s, _)| (n, format!("{:?}", s))))
-        .collect::<HashMap<_, _>>();
-
+    let edges = check_graph(&layering_graph, &layer_topsort).transitive_reduction;
     let mut layer_graph = LayerGraph::default();
     for layer in &layers_vec {
         for &node in layer {
-            if node.is_system() {
-                layer_graph.add_node(node, LayerNode::System(node_names[&node].clone()));
-            } else if node.is_set() {
-                // TODO: Add a sub layer graph
-                layer_graph.add_node(
-                    node,
-                    LayerNode::Set(node_names[&node].clone(), LayerGraph::default()),
-                );
+            let name = info.drawn_name(node);
+            match node {
+                DrawnNode::Node(system) if system.is_system() => {
+                    // It's in several sets that are drawn apart, so it's drawn on its own
+                    let mut sets: Vec<_> = outermost_sets(hierarchy, &dissolved, system)
+                        .into_iter()
+                        .map(|set| info.name(set))
+                        .collect();
+                    sets.sort();
+                    let name = if sets.len() > 1 {
+                        format!("{} (in {})", name, sets.join(" & "))
+                    } else {
+                        name
+                    };
+                    layer_graph.add_node(node, LayerNode::System(name));
+                }
+                _ => {
+                    let name = if node
+                        .node()
+                        .is_some_and(|set| info.empty_sets.contains(&set))
+                    {
+                        format!("{} (empty)", name)
+                    } else {
+                        name
+                    };
+                    let inner = match node {
+                        DrawnNode::Node(set) => {
+                            let drawn_inside =
+                                |system| drawn_as(hierarchy, info, &dissolved, system) == node;
+                            let (dependencies, hierarchy) =
+                                inside_set(dependencies, hierarchy, set, &drawn_inside);
+                            layout_level(&dependencies, &hierarchy, info, None)?
+                        }
+                        // Crate groups and chains are only drawn closed
+                        _ => LayerGraph::default(),
+                    };
+                    layer_graph.add_node(node, LayerNode::Set(name, inner));
+                }
             }
-
             layer_graph.add_edges(
                 node,
-                graph_map
+                edges
                     .neighbors_directed(node, petgraph::Direction::Outgoing)
                     .collect(),
             );
@@ -243,5 +778,297 @@ fn schedule_graph_layout(schedule: &Schedule) -> LayerGraph {
     }
     layer_graph.layers = layers_vec;
 
-    layer_graph
+    // Ordering from `configure_sets`, drawn between the nodes the sets are drawn in
+    let drawn = |node| match layer_graph.contains(&DrawnNode::Node(node)) {
+        true => DrawnNode::Node(node),
+        false => drawn_as(hierarchy, info, &dissolved, node),
+    };
+    let mut set_edges = Vec::new();
+    for (a, b, ()) in dependencies.all_edges() {
+        if !a.is_set() || !b.is_set() {
+            continue;
+        }
+        let (a, b) = (drawn(a), drawn(b));
+        if a != b
+            && layer_graph.contains(&a)
+            && layer_graph.contains(&b)
+            && !set_edges.contains(&(a, b))
+        {
+            set_edges.push((a, b));
+        }
+    }
+    layer_graph.set_edges = set_edges;
+
+    Ok(layer_graph)
+}
+
+/// The ordering and hierarchy of everything drawn inside a set, leaving out the set itself so the
+/// sets directly in it are the outermost ones. Its systems that are drawn somewhere else (beside
+/// it, as they're in other sets too, or in a crate group or chain) are left out
+fn inside_set(
+    dependencies: &DiGraphMap<NodeId, ()>,
+    hierarchy: &DiGraphMap<NodeId, ()>,
+    set: NodeId,
+    drawn_inside: &dyn Fn(NodeId) -> bool,
+) -> (DiGraphMap<NodeId, ()>, DiGraphMap<NodeId, ()>) {
+    let mut inside = HashSet::new();
+    let mut dfs = Dfs::new(hierarchy, set);
+    while let Some(node) = dfs.next(hierarchy) {
+        if node != set && (node.is_set() || drawn_inside(node)) {
+            inside.insert(node);
+        }
+    }
+    let keep = |graph: &DiGraphMap<NodeId, ()>| {
+        let mut kept = DiGraphMap::new();
+        for node in graph.nodes().filter(|node| inside.contains(node)) {
+            kept.add_node(node);
+        }
+        for (a, b, ()) in graph.all_edges() {
+            if inside.contains(&a) && inside.contains(&b) {
+                kept.add_edge(a, b, ());
+            }
+        }
+        kept
+    };
+    (keep(dependencies), keep(hierarchy))
+}
+
+/// The outermost sets a node is inside, looking through the dissolved ones
+fn outermost_sets(
+    hierarchy: &DiGraphMap<NodeId, ()>,
+    dissolved: &HashSet<NodeId>,
+    node: NodeId,
+) -> HashSet<NodeId> {
+    let mut sets = HashSet::new();
+    for parent in hierarchy.neighbors_directed(node, petgraph::Direction::Incoming) {
+        let above = outermost_sets(hierarchy, dissolved, parent);
+        if !above.is_empty() {
+            sets.extend(above);
+        } else if !dissolved.contains(&parent) {
+            sets.insert(parent);
+        }
+    }
+    sets
+}
+
+/// The node a node is drawn as: the outermost set it's in, or itself if that isn't just one. A
+/// crate's systems are always drawn inside the crate's node
+fn drawn_as(
+    hierarchy: &DiGraphMap<NodeId, ()>,
+    info: &ScheduleInfo,
+    dissolved: &HashSet<NodeId>,
+    node: NodeId,
+) -> DrawnNode {
+    if let Some(&group) = info.grouped_systems.get(&node) {
+        return group;
+    }
+    let sets = outermost_sets(hierarchy, dissolved, node);
+    match sets.len() {
+        1 => DrawnNode::Node(sets.into_iter().next().unwrap()),
+        _ => DrawnNode::Node(node),
+    }
+}
+
+/// The ordering between the drawn nodes, from the ordering of everything inside them
+fn layering_graph(
+    dependencies: &DiGraphMap<NodeId, ()>,
+    hierarchy: &DiGraphMap<NodeId, ()>,
+    info: &ScheduleInfo,
+    dissolved: &HashSet<NodeId>,
+) -> DiGraphMap<DrawnNode, ()> {
+    let mut cache: HashMap<NodeId, Vec<DrawnNode>> = HashMap::new();
+    let mut drawn = |node: NodeId| -> Vec<DrawnNode> {
+        cache
+            .entry(node)
+            .or_insert_with(|| {
+                let systems = set_systems(hierarchy, node);
+                // Empty sets are drawn on their own, in whatever set they're in
+                let mut drawn: Vec<_> = if systems.is_empty() {
+                    vec![drawn_as(hierarchy, info, dissolved, node)]
+                } else {
+                    systems
+                        .into_iter()
+                        .map(|system| drawn_as(hierarchy, info, dissolved, system))
+                        .collect()
+                };
+                drawn.sort();
+                drawn.dedup();
+                drawn
+            })
+            .clone()
+    };
+
+    let mut layering_graph = DiGraphMap::new();
+    for node in dependencies.nodes() {
+        for drawn_node in drawn(node) {
+            layering_graph.add_node(drawn_node);
+        }
+    }
+    for (a, b, ()) in dependencies.all_edges() {
+        let b_drawn = drawn(b);
+        for a_drawn in drawn(a) {
+            for &b_drawn in &b_drawn {
+                // Ordering inside a node isn't drawn
+                if a_drawn != b_drawn {
+                    layering_graph.add_edge(a_drawn, b_drawn, ());
+                }
+            }
+        }
+    }
+    layering_graph
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+    struct Between;
+
+    #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+    enum Nested {
+        Outer,
+        Inner,
+        Left,
+        Right,
+    }
+
+    fn first() {}
+    fn second() {}
+    fn third() {}
+    fn fourth() {}
+
+    fn layout(app: &mut App, settings: &LayoutSettings) -> (LayerGraph, ScheduleInfo) {
+        app.update();
+        let schedule = app.world.resource::<Schedules>().get(Update).unwrap();
+        schedule_graph_layout(schedule, settings).unwrap()
+    }
+
+    fn layer_names(layer_graph: &LayerGraph) -> Vec<Vec<String>> {
+        layer_graph
+            .layers
+            .iter()
+            .map(|layer| {
+                let mut names: Vec<_> = layer.iter().map(|n| layer_graph.node_name(n)).collect();
+                names.sort();
+                names
+            })
+            .collect()
+    }
+
+    fn inner<'a>(layer_graph: &'a LayerGraph, name: &str) -> &'a LayerGraph {
+        let node = layer_graph
+            .layers
+            .iter()
+            .flatten()
+            .find(|n| layer_graph.node_name(n) == name)
+            .unwrap_or_else(|| panic!("No node called {name} in {:?}", layer_names(layer_graph)));
+        layer_graph.inner(node).unwrap()
+    }
+
+    #[test]
+    fn nested_sets() {
+        let mut app = App::new();
+        app.configure_sets(Update, Nested::Inner.in_set(Nested::Outer))
+            .add_systems(
+                Update,
+                (
+                    (first, second).in_set(Nested::Inner),
+                    third.in_set(Nested::Outer).after(Nested::Inner),
+                    fourth.after(Nested::Outer),
+                ),
+            );
+        let (layer_graph, _) = layout(&mut app, &LayoutSettings::default());
+        assert_eq!(layer_names(&layer_graph), [["Outer"], ["fourth"]]);
+        let outer = inner(&layer_graph, "Outer");
+        assert_eq!(layer_names(outer), [["Inner"], ["third"]]);
+        assert_eq!(layer_names(inner(outer, "Inner")), [["first", "second"]]);
+    }
+
+    #[test]
+    fn overlapping_sets() {
+        let mut app = App::new();
+        app.configure_sets(Update, (Nested::Left, Nested::Right).in_set(Nested::Outer))
+            .add_systems(
+                Update,
+                (
+                    first.in_set(Nested::Left),
+                    second.in_set(Nested::Left).in_set(Nested::Right),
+                    third.in_set(Nested::Right),
+                ),
+            );
+        let (layer_graph, _) = layout(&mut app, &LayoutSettings::default());
+        assert_eq!(layer_names(&layer_graph), [["Outer"]]);
+        // Drawn beside both of the sets it's in rather than inside either
+        let outer = inner(&layer_graph, "Outer");
+        assert_eq!(
+            layer_names(outer),
+            [["Left", "Right", "second (in Left & Right)"]]
+        );
+        assert_eq!(layer_names(inner(outer, "Left")), [["first"]]);
+        assert_eq!(layer_names(inner(outer, "Right")), [["third"]]);
+    }
+
+    #[test]
+    fn dissolved_sets() {
+        let mut app = App::new();
+        app.configure_sets(Update, Nested::Inner.in_set(Nested::Outer))
+            .add_systems(
+                Update,
+                (
+                    (first, third).in_set(Nested::Inner),
+                    // Has to run in the middle of Inner, so Inner can't be drawn as one node
+                    second.in_set(Nested::Outer).after(first).before(third),
+                    fourth.after(first).before(third),
+                ),
+            );
+        let (layer_graph, _) = layout(&mut app, &LayoutSettings::default());
+        // fourth is in the middle of Outer too
+        assert_eq!(
+            layer_names(&layer_graph),
+            [vec!["first"], vec!["fourth", "second"], vec!["third"]]
+        );
+        assert!(layer_graph
+            .layers
+            .iter()
+            .flatten()
+            .all(|n| layer_graph.inner(n).is_none()));
+
+        let mut app = App::new();
+        app.configure_sets(Update, Nested::Inner.in_set(Nested::Outer))
+            .add_systems(
+                Update,
+                (
+                    (first, third).in_set(Nested::Inner),
+                    second.in_set(Nested::Outer).after(first).before(third),
+                    fourth.after(Nested::Outer),
+                ),
+            );
+        let (layer_graph, _) = layout(&mut app, &LayoutSettings::default());
+        assert_eq!(layer_names(&layer_graph), [["Outer"], ["fourth"]]);
+        assert_eq!(
+            layer_names(inner(&layer_graph, "Outer")),
+            [["first"], ["second"], ["third"]]
+        );
+    }
+
+    #[test]
+    fn crate_not_grouped_around_empty_set() {
+        let mut app = App::new();
+        app.configure_sets(Update, Between.after(first).before(second))
+            .add_systems(Update, (first, second));
+        let settings = LayoutSettings {
+            grouping: CrateGrouping {
+                enabled: true,
+                ..default()
+            },
+            ..default()
+        };
+        let (layer_graph, info) = layout(&mut app, &settings);
+        assert!(info.crate_groups.is_empty());
+        assert_eq!(
+            layer_names(&layer_graph),
+            [["first"], ["Between (empty)"], ["second"]]
+        );
+    }
 }
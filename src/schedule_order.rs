@@ -0,0 +1,287 @@
+use bevy::{
+    app::{FixedMain, FixedMainScheduleOrder, InternedAppLabel, MainScheduleOrder},
+    ecs::schedule::{InternedScheduleLabel, ScheduleLabel},
+    prelude::*,
+    render::{camera::RenderTarget, ExtractSchedule},
+    ui::TargetCamera,
+    utils::HashSet,
+    window::WindowRef,
+};
+
+use crate::{graph_refresh::ShowSchedule, schedule_graph::ScheduleInfo, sub_apps::SubAppGraphs};
+
+const SCHEDULE_COLOUR: Color = Color::rgb(0.35, 0.35, 0.45);
+const GRAPHED_COLOUR: Color = Color::rgb(0.3, 0.5, 0.9);
+
+/// Schedules that are run one after another by the same thing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduleRow {
+    pub title: String,
+    /// The sub-app they are in, `None` for the main app
+    pub app: Option<InternedAppLabel>,
+    pub schedules: Vec<InternedScheduleLabel>,
+    /// Each schedule's name and how many systems it has
+    pub descriptions: Vec<String>,
+}
+
+fn describe_schedule(label: InternedScheduleLabel, systems: Option<Option<usize>>) -> String {
+    match systems {
+        Some(Some(systems)) => format!("{:?} ({})", label, systems),
+        Some(None) => format!("{:?} (not run yet)", label),
+        None => format!("{:?} (empty)", label),
+    }
+}
+
+/// The schedules that make up a frame in the order they run, including the ones run inside
+/// other schedules and by sub-apps
+pub fn schedule_rows(
+    schedules: &Schedules,
+    main: &MainScheduleOrder,
+    fixed_main: &FixedMainScheduleOrder,
+    sub_apps: &SubAppGraphs,
+) -> Vec<ScheduleRow> {
+    let mut rows = vec![
+        ("Main, the first time it runs", main.startup_labels.clone()),
+        ("Main, every frame", main.labels.clone()),
+        (
+            "FixedMain, run by RunFixedMainLoop any number of times a frame",
+            fixed_main.labels.clone(),
+        ),
+    ];
+
+    let listed: HashSet<_> = rows
+        .iter()
+        .flat_map(|(_, labels)| labels.iter().copied())
+        .chain([Main.intern(), FixedMain.intern()])
+        .collect();
+    let mut others: Vec<_> = schedules
+        .iter()
+        .map(|(_, schedule)| schedule.label())
+        .filter(|label| !listed.contains(label))
+        .collect();
+    others.sort_by_key(|label| format!("{:?}", label));
+    if !others.is_empty() {
+        rows.push((
+            "Run some other way (by systems, state transitions, ...)",
+            others,
+        ));
+    }
+
+    let mut rows: Vec<_> = rows
+        .into_iter()
+        .map(|(title, labels)| ScheduleRow {
+            title: title.to_string(),
+            app: None,
+            descriptions: labels
+                .iter()
+                .map(|&label| {
+                    let systems = schedules
+                        .get(label)
+                        .map(|s| s.systems().is_ok().then(|| s.systems_len()));
+                    describe_schedule(label, systems)
+                })
+                .collect(),
+            schedules: labels,
+        })
+        .collect();
+
+    for (app, snapshot) in &sub_apps.0 {
+        let snapshot = snapshot.lock().unwrap();
+        let labels = snapshot.ordered_schedules();
+        let title = if labels.contains(&ExtractSchedule.intern()) {
+            format!(
+                "{:?}, after each main app frame (ExtractSchedule copies from the main world)",
+                app
+            )
+        } else {
+            format!("{:?}, after each main app frame", app)
+        };
+        rows.push(ScheduleRow {
+            title,
+            app: Some(*app),
+            descriptions: labels
+                .iter()
+                .map(|label| describe_schedule(*label, snapshot.schedules.get(label).copied()))
+                .collect(),
+            schedules: labels,
+        });
+    }
+
+    rows
+}
+
+/// The window showing every schedule in order, toggled with O
+#[derive(Resource, Debug, Default)]
+pub struct ScheduleOrderView {
+    pub shown: bool,
+    pub rows: Vec<ScheduleRow>,
+    window: Option<Entity>,
+    camera: Option<Entity>,
+    root: Option<Entity>,
+}
+
+#[derive(Component)]
+pub struct ScheduleButton(pub Option<InternedAppLabel>, pub InternedScheduleLabel);
+
+pub fn toggle_schedule_order(keys: Res<ButtonInput<KeyCode>>, mut view: ResMut<ScheduleOrderView>) {
+    if keys.just_pressed(KeyCode::KeyO) {
+        view.shown = !view.shown;
+    }
+}
+
+/// Runs in `Main`, where all the schedules it runs can be seen
+pub fn update_schedule_order(
+    mut view: ResMut<ScheduleOrderView>,
+    schedules: Res<Schedules>,
+    main: Res<MainScheduleOrder>,
+    fixed_main: Res<FixedMainScheduleOrder>,
+    sub_apps: Res<SubAppGraphs>,
+) {
+    if !view.shown {
+        return;
+    }
+    let rows = schedule_rows(&schedules, &main, &fixed_main, &sub_apps);
+    if rows != view.rows {
+        view.rows = rows;
+    }
+}
+
+pub fn draw_schedule_order(
+    mut commands: Commands,
+    mut view: ResMut<ScheduleOrderView>,
+    info: Res<ScheduleInfo>,
+) {
+    if !view.is_changed() && !info.is_changed() {
+        return;
+    }
+    let view = view.bypass_change_detection();
+
+    if let Some(root) = view.root.take() {
+        commands.entity(root).despawn_recursive();
+    }
+    if !view.shown {
+        for entity in [view.camera.take(), view.window.take()]
+            .into_iter()
+            .flatten()
+        {
+            commands.entity(entity).despawn_recursive();
+        }
+        return;
+    }
+
+    let camera = match view.camera {
+        Some(camera) => camera,
+        None => {
+            let window = commands
+                .spawn(Window {
+                    title: "Schedule Order".to_string(),
+                    ..default()
+                })
+                .id();
+            let camera = commands
+                .spawn(Camera2dBundle {
+                    camera: Camera {
+                        target: RenderTarget::Window(WindowRef::Entity(window)),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .id();
+            view.window = Some(window);
+            view.camera = Some(camera);
+            camera
+        }
+    };
+
+    let text_style = |font_size| TextStyle {
+        font_size,
+        color: Color::WHITE,
+        ..default()
+    };
+    let root = commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Column,
+                    padding: UiRect::all(Val::Px(10.0)),
+                    row_gap: Val::Px(5.0),
+                    ..default()
+                },
+                ..default()
+            },
+            TargetCamera(camera),
+        ))
+        .with_children(|builder| {
+            builder.spawn(TextBundle::from_section(
+                "Click a schedule to graph it (O to close)",
+                text_style(20.0),
+            ));
+            for row in &view.rows {
+                builder.spawn(TextBundle::from_section(&row.title, text_style(18.0)));
+                builder
+                    .spawn(NodeBundle {
+                        style: Style {
+                            flex_wrap: FlexWrap::Wrap,
+                            align_items: AlignItems::Center,
+                            column_gap: Val::Px(5.0),
+                            row_gap: Val::Px(5.0),
+                            margin: UiRect::bottom(Val::Px(10.0)),
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|builder| {
+                        for (i, (&label, description)) in
+                            row.schedules.iter().zip(&row.descriptions).enumerate()
+                        {
+                            if i > 0 {
+                                builder.spawn(TextBundle::from_section("->", text_style(18.0)));
+                            }
+
+                            builder
+                                .spawn((
+                                    NodeBundle {
+                                        style: Style {
+                                            padding: UiRect::axes(Val::Px(5.0), Val::Px(1.0)),
+                                            ..default()
+                                        },
+                                        background_color: if (row.app, label)
+                                            == (info.app, info.schedule)
+                                        {
+                                            GRAPHED_COLOUR
+                                        } else {
+                                            SCHEDULE_COLOUR
+                                        }
+                                        .into(),
+                                        ..default()
+                                    },
+                                    Interaction::default(),
+                                    ScheduleButton(row.app, label),
+                                ))
+                                .with_children(|builder| {
+                                    builder.spawn(TextBundle::from_section(
+                                        description.clone(),
+                                        text_style(18.0),
+                                    ));
+                                });
+                        }
+                    });
+            }
+        })
+        .id();
+    view.root = Some(root);
+}
+
+pub fn select_schedule(
+    buttons: Query<(&Interaction, &ScheduleButton), Changed<Interaction>>,
+    mut show: EventWriter<ShowSchedule>,
+) {
+    for (interaction, button) in &buttons {
+        if *interaction == Interaction::Pressed {
+            show.send(ShowSchedule {
+                app: button.0,
+                schedule: button.1,
+            });
+        }
+    }
+}
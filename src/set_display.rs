@@ -0,0 +1,52 @@
+use bevy::{ecs::schedule::NodeId, prelude::*};
+
+use crate::schedule_graph::ScheduleInfo;
+
+/// Which sets are drawn, and which are collapsed into the systems in them
+#[derive(Resource, Debug, Clone, PartialEq, Eq)]
+pub struct SetDisplay {
+    /// Leave out sets with no systems in them, keeping what they order ordered (X)
+    pub hide_empty: bool,
+    /// Sets with just one system in them
+    pub collapse_single: bool,
+    /// The sets bevy makes for each system type, whatever's in them
+    pub collapse_system_type_sets: bool,
+    /// The sets bevy makes for `(a, b).run_if(..)`
+    pub collapse_anonymous: bool,
+}
+
+impl Default for SetDisplay {
+    fn default() -> Self {
+        SetDisplay {
+            hide_empty: false,
+            collapse_single: true,
+            collapse_system_type_sets: true,
+            collapse_anonymous: false,
+        }
+    }
+}
+
+impl SetDisplay {
+    /// Draw every set, however little is in it
+    pub fn keep_all() -> Self {
+        SetDisplay {
+            collapse_single: false,
+            collapse_system_type_sets: false,
+            ..default()
+        }
+    }
+
+    pub fn collapses(&self, info: &ScheduleInfo, set: NodeId, systems: usize) -> bool {
+        if info.system_type_sets.contains(&set) {
+            return self.collapse_system_type_sets;
+        }
+        (self.collapse_single && systems == 1)
+            || (self.collapse_anonymous && info.anonymous_sets.contains(&set))
+    }
+}
+
+pub fn set_display_controls(keys: Res<ButtonInput<KeyCode>>, mut display: ResMut<SetDisplay>) {
+    if keys.just_pressed(KeyCode::KeyX) {
+        display.hide_empty = !display.hide_empty;
+    }
+}
@@ -1,92 +1,1112 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, fmt};
 
-use bevy::{ecs::schedule::NodeId, utils::HashMap};
+use bevy::{ecs::schedule::NodeId, prelude::*, utils::HashMap};
 
-#[derive(Debug)]
-pub struct FunctionName {
-    sections: Vec<Vec<String>>, // Sections (things like <>), split into blocks (::) internally
+const CLOSURE: &str = "{{closure}}";
+/// How closures are shown, numbered `{closure#2}` when a function has more than one
+const READABLE_CLOSURE: &str = "{closure}";
+
+/// A parsed `std::any::type_name`, so it can be printed with more or less detail
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeName {
+    /// `a::b::C<T>::d`
+    Path(Vec<PathSegment>),
+    /// `<T as Trait>::rest`, or `<T>::rest`
+    Qualified {
+        self_type: Box<TypeName>,
+        as_trait: Option<Box<TypeName>>,
+        rest: Vec<PathSegment>,
+    },
+    Reference {
+        mutable: bool,
+        inner: Box<TypeName>,
+    },
+    Pointer {
+        mutable: bool,
+        inner: Box<TypeName>,
+    },
+    Tuple(Vec<TypeName>),
+    Slice(Box<TypeName>),
+    Array(Box<TypeName>, String),
+    /// `dyn A + B`
+    Dyn(Vec<TypeName>),
+    /// `impl A + B`
+    Impl(Vec<TypeName>),
+    /// `fn(A) -> B`, with any `unsafe` or `extern` before it
+    FnPointer {
+        qualifiers: String,
+        params: Vec<TypeName>,
+        output: Option<Box<TypeName>>,
+    },
+    /// Anything else, like `!`, a lifetime or a const generic
+    Other(String),
 }
 
-impl FunctionName {
-    // pub fn shorten_str(full_name: &str) -> String {
-    //     if let Some(before) = full_name.strip_suffix("::{{closure}}") {
-    //         return format!("{}::{{{{closure}}}}", Self::shorten_str(before));
-    //     }
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathSegment {
+    pub name: String,
+    pub generics: Generics,
+}
 
-    //     let mut short_name = String::new();
-    //     for path in full_name.split_inclusive(&['<', '>', '(', ')', '[', ']', ',', ';'][..]) {
-    //         // Push the shortened path in front of the found character
-    //         short_name.push_str(path.rsplit(':').next().unwrap().trim());
-    //     }
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Generics {
+    None,
+    /// `<A, B>`
+    Angle(Vec<TypeName>),
+    /// `(A, B) -> C`, from `Fn` traits
+    Parenthesized(Vec<TypeName>, Option<Box<TypeName>>),
+}
 
-    //     short_name
-    // }
+impl Generics {
+    fn types(&self) -> impl Iterator<Item = &TypeName> {
+        let (types, output): (&[TypeName], _) = match self {
+            Generics::None => (&[], None),
+            Generics::Angle(types) => (types, None),
+            Generics::Parenthesized(types, output) => (types, output.as_deref()),
+        };
+        types.iter().chain(output)
+    }
+}
 
-    pub fn new(full_name: &str) -> FunctionName {
-        if let Some(before) = full_name.strip_suffix("::{{closure}}") {
-            FunctionName::new(before)
-        } else {
-            FunctionName {
-                sections: full_name
-                    .split_inclusive(&['<', '>', '(', ')', '[', ']', ',', ';'][..])
-                    .map(|s| {
-                        s.trim()
-                            .split("::")
-                            .map(|s| s.to_string())
-                            .collect::<Vec<_>>()
-                    })
-                    .collect(),
+/// How many segments of a path can be left off the front, closures count as part of the function
+/// they're in
+pub fn named_len(path: &[PathSegment]) -> usize {
+    let closures = path.iter().rev().take_while(|s| s.name == CLOSURE).count();
+    path.len() - closures
+}
+
+struct Parser<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    fn eat(&mut self, token: &str) -> bool {
+        match self.rest.strip_prefix(token) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn skip_spaces(&mut self) {
+        self.rest = self.rest.trim_start();
+    }
+
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> &'a str {
+        let end = self.rest.find(|c| !f(c)).unwrap_or(self.rest.len());
+        let (taken, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        taken
+    }
+
+    fn ident(&mut self) -> &'a str {
+        self.take_while(|c| c.is_alphanumeric() || matches!(c, '_' | '{' | '}'))
+    }
+
+    /// Types separated by commas up to `close`
+    fn list(&mut self, close: &str) -> Vec<TypeName> {
+        let mut types = Vec::new();
+        loop {
+            self.skip_spaces();
+            if self.rest.is_empty() || self.eat(close) {
+                return types;
+            }
+            let before = self.rest.len();
+            types.push(self.parse_type());
+            self.skip_spaces();
+            self.eat(",");
+            if self.rest.len() == before {
+                // Something it doesn't understand, skip it rather than looping forever
+                let mut chars = self.rest.chars();
+                types.push(TypeName::Other(chars.next().unwrap().to_string()));
+                self.rest = chars.as_str();
+            }
+        }
+    }
+
+    /// Types separated by ` + `
+    fn bounds(&mut self) -> Vec<TypeName> {
+        let mut bounds = vec![self.parse_type()];
+        while self.eat(" + ") {
+            bounds.push(self.parse_type());
+        }
+        bounds
+    }
+
+    fn path(&mut self) -> Vec<PathSegment> {
+        let mut segments = Vec::new();
+        loop {
+            let name = self.ident().to_string();
+            let generics = if self.eat("<") {
+                Generics::Angle(self.list(">"))
+            } else if self.eat("(") {
+                let params = self.list(")");
+                let output = self.eat(" -> ").then(|| Box::new(self.parse_type()));
+                Generics::Parenthesized(params, output)
+            } else {
+                Generics::None
+            };
+            segments.push(PathSegment { name, generics });
+            if !self.eat("::") {
+                return segments;
             }
         }
     }
 
-    fn shortest(&self) -> String {
-        let mut short_name = String::new();
-        for sect in &self.sections {
-            short_name.push_str(sect.last().unwrap());
+    fn parse_type(&mut self) -> TypeName {
+        self.skip_spaces();
+        if self.eat("&") {
+            if self.rest.starts_with('\'') {
+                self.eat("'");
+                self.ident();
+                self.skip_spaces();
+            }
+            let mutable = self.eat("mut ");
+            TypeName::Reference {
+                mutable,
+                inner: Box::new(self.parse_type()),
+            }
+        } else if self.eat("*const ") || self.rest.starts_with("*mut ") {
+            let mutable = self.eat("*mut ");
+            TypeName::Pointer {
+                mutable,
+                inner: Box::new(self.parse_type()),
+            }
+        } else if self.eat("(") {
+            TypeName::Tuple(self.list(")"))
+        } else if self.eat("[") {
+            let inner = Box::new(self.parse_type());
+            if self.eat(";") {
+                let len = self.take_while(|c| c != ']').trim().to_string();
+                self.eat("]");
+                TypeName::Array(inner, len)
+            } else {
+                self.eat("]");
+                TypeName::Slice(inner)
+            }
+        } else if self.eat("dyn ") {
+            TypeName::Dyn(self.bounds())
+        } else if self.eat("impl ") {
+            TypeName::Impl(self.bounds())
+        } else if let Some(fn_start) = self.rest.find("fn(").filter(|&i| {
+            let qualifiers = &self.rest[..i];
+            qualifiers.is_empty()
+                || (qualifiers.starts_with("unsafe ") || qualifiers.starts_with("extern "))
+                    && !qualifiers.contains(['<', ',', '(', ')', '>'])
+        }) {
+            let qualifiers = self.rest[..fn_start].to_string();
+            self.rest = &self.rest[fn_start + "fn(".len()..];
+            let params = self.list(")");
+            let output = self.eat(" -> ").then(|| Box::new(self.parse_type()));
+            TypeName::FnPointer {
+                qualifiers,
+                params,
+                output,
+            }
+        } else if self.eat("<") {
+            let self_type = Box::new(self.parse_type());
+            let as_trait = self.eat(" as ").then(|| Box::new(self.parse_type()));
+            self.eat(">");
+            let rest = if self.eat("::") {
+                self.path()
+            } else {
+                Vec::new()
+            };
+            TypeName::Qualified {
+                self_type,
+                as_trait,
+                rest,
+            }
+        } else if self.rest.starts_with('\'') {
+            self.eat("'");
+            TypeName::Other(format!("'{}", self.ident()))
+        } else if self
+            .rest
+            .starts_with(|c: char| c.is_alphanumeric() || matches!(c, '_' | '{'))
+        {
+            TypeName::Path(self.path())
+        } else {
+            TypeName::Other(
+                self.take_while(|c| !matches!(c, ',' | '>' | ')' | ']' | ';'))
+                    .to_string(),
+            )
         }
-        short_name
     }
 }
 
-// Just a test of some algos
-pub fn shorten_pair(func1: FunctionName, func2: FunctionName) -> (String, String) {
-    let mut func1name = String::new();
-    let mut func2name = String::new();
-    for (sect1, sect2) in func1.sections.into_iter().zip(func2.sections) {
-        if sect1 != sect2 {
-            let mut block = Vec::new();
-            for (block1, block2) in sect1.into_iter().rev().zip(sect2.into_iter().rev()) {
-                if block1 == block2 {
-                    block.push(block1);
-                } else {
-                    block.reverse();
-                    let block_part = &(String::from("::") + &block.join("::"));
-                    func1name.push_str(&(block1 + block_part));
-                    func2name.push_str(&(block2 + block_part));
+impl TypeName {
+    pub fn parse(full_name: &str) -> TypeName {
+        let mut parser = Parser { rest: full_name };
+        let name = parser.parse_type();
+        if parser.rest.is_empty() {
+            name
+        } else {
+            // Not a type name after all, keep it as it is
+            TypeName::Other(full_name.to_string())
+        }
+    }
+
+    /// Every path in it, outermost first then left to right
+    pub fn paths(&self) -> Vec<&[PathSegment]> {
+        let mut paths = Vec::new();
+        self.visit_paths(&mut |path| paths.push(path));
+        paths
+    }
+
+    fn visit_paths<'a>(&'a self, f: &mut dyn FnMut(&'a [PathSegment])) {
+        let visit_path = |path: &'a [PathSegment], f: &mut dyn FnMut(&'a [PathSegment])| {
+            f(path);
+            for segment in path {
+                for generic in segment.generics.types() {
+                    generic.visit_paths(f);
                 }
             }
-        } else {
-            // These are the same so just get the last item
-            func1name.push_str(sect1.last().unwrap());
-            func2name.push_str(sect2.last().unwrap());
+        };
+        match self {
+            TypeName::Path(path) => visit_path(path, f),
+            TypeName::Qualified {
+                self_type,
+                as_trait,
+                rest,
+            } => {
+                self_type.visit_paths(f);
+                if let Some(as_trait) = as_trait {
+                    as_trait.visit_paths(f);
+                }
+                visit_path(rest, f);
+            }
+            TypeName::Reference { inner, .. }
+            | TypeName::Pointer { inner, .. }
+            | TypeName::Slice(inner)
+            | TypeName::Array(inner, _) => inner.visit_paths(f),
+            TypeName::Tuple(types) | TypeName::Dyn(types) | TypeName::Impl(types) => {
+                for t in types {
+                    t.visit_paths(f);
+                }
+            }
+            TypeName::FnPointer { params, output, .. } => {
+                for t in params.iter().chain(output.as_deref()) {
+                    t.visit_paths(f);
+                }
+            }
+            TypeName::Other(_) => {}
+        }
+    }
+
+    /// The crate it's from, the start of the first path in it
+    pub fn crate_name(&self) -> &str {
+        self.paths()
+            .first()
+            .and_then(|path| path.first())
+            .map_or("", |segment| &segment.name)
+    }
+
+    /// Just the last segment of each path
+    pub fn shortest(&self) -> String {
+        self.print(&|_, _| 1, false)
+    }
+
+    /// Prints each path with `depth(index, path)` of its last segments, indexed in the order
+    /// `paths` gives them, and generic arguments as `..` if `collapse_generics`
+    pub fn print(
+        &self,
+        depth: &dyn Fn(usize, &[PathSegment]) -> usize,
+        collapse_generics: bool,
+    ) -> String {
+        let mut printer = Printer::new(depth, collapse_generics);
+        printer.name(self);
+        printer.out
+    }
+}
+
+/// The whole name, as `type_name` would give it
+impl fmt::Display for TypeName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut printer = Printer::new(&|_, path| path.len(), false);
+        printer.exact = true;
+        printer.name(self);
+        f.write_str(&printer.out)
+    }
+}
+
+struct Printer<'a> {
+    out: String,
+    /// Of the next path
+    index: usize,
+    depth: &'a dyn Fn(usize, &[PathSegment]) -> usize,
+    collapse_generics: bool,
+    /// Closures as `type_name` writes them rather than `{closure}`
+    exact: bool,
+}
+
+impl<'a> Printer<'a> {
+    fn new(depth: &'a dyn Fn(usize, &[PathSegment]) -> usize, collapse_generics: bool) -> Self {
+        Printer {
+            out: String::new(),
+            index: 0,
+            depth,
+            collapse_generics,
+            exact: false,
+        }
+    }
+
+    fn list(&mut self, types: &[TypeName], separator: &str) {
+        for (i, t) in types.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(separator);
+            }
+            self.name(t);
+        }
+    }
+
+    /// Skips over the paths that aren't printed so the indices still line up
+    fn skip(&mut self, types: impl Iterator<Item = impl std::ops::Deref<Target = TypeName>>) {
+        for t in types {
+            self.index += t.paths().len();
+        }
+    }
+
+    fn path(&mut self, path: &[PathSegment]) {
+        let named = named_len(path);
+        let depth = (self.depth)(self.index, path).clamp(1, named.max(1));
+        self.index += 1;
+
+        let shown = named.saturating_sub(depth);
+        for segment in &path[..shown] {
+            self.skip(segment.generics.types());
+        }
+        for (i, segment) in path[shown..].iter().enumerate() {
+            if i > 0 {
+                self.out.push_str("::");
+            }
+            if segment.name == CLOSURE && !self.exact {
+                self.out.push_str(READABLE_CLOSURE);
+            } else {
+                self.out.push_str(&segment.name);
+            }
+            match &segment.generics {
+                Generics::None => {}
+                generics if self.collapse_generics => {
+                    self.out.push_str(match generics {
+                        Generics::Parenthesized(..) => "(..)",
+                        _ => "<..>",
+                    });
+                    self.skip(generics.types());
+                }
+                Generics::Angle(types) => {
+                    self.out.push('<');
+                    self.list(types, ", ");
+                    self.out.push('>');
+                }
+                Generics::Parenthesized(params, output) => {
+                    self.out.push('(');
+                    self.list(params, ", ");
+                    self.out.push(')');
+                    if let Some(output) = output {
+                        self.out.push_str(" -> ");
+                        self.name(output);
+                    }
+                }
+            }
+        }
+    }
+
+    fn name(&mut self, name: &TypeName) {
+        match name {
+            TypeName::Path(path) => self.path(path),
+            TypeName::Qualified {
+                self_type,
+                as_trait,
+                rest,
+            } => {
+                self.out.push('<');
+                self.name(self_type);
+                if let Some(as_trait) = as_trait {
+                    self.out.push_str(" as ");
+                    self.name(as_trait);
+                }
+                self.out.push('>');
+                if !rest.is_empty() {
+                    self.out.push_str("::");
+                    self.path(rest);
+                }
+            }
+            TypeName::Reference { mutable, inner } => {
+                self.out.push_str(if *mutable { "&mut " } else { "&" });
+                self.name(inner);
+            }
+            TypeName::Pointer { mutable, inner } => {
+                self.out
+                    .push_str(if *mutable { "*mut " } else { "*const " });
+                self.name(inner);
+            }
+            TypeName::Tuple(types) => {
+                self.out.push('(');
+                self.list(types, ", ");
+                if types.len() == 1 {
+                    self.out.push(',');
+                }
+                self.out.push(')');
+            }
+            TypeName::Slice(inner) => {
+                self.out.push('[');
+                self.name(inner);
+                self.out.push(']');
+            }
+            TypeName::Array(inner, len) => {
+                self.out.push('[');
+                self.name(inner);
+                self.out.push_str("; ");
+                self.out.push_str(len);
+                self.out.push(']');
+            }
+            TypeName::Dyn(bounds) => {
+                self.out.push_str("dyn ");
+                self.list(bounds, " + ");
+            }
+            TypeName::Impl(bounds) => {
+                self.out.push_str("impl ");
+                self.list(bounds, " + ");
+            }
+            TypeName::FnPointer {
+                qualifiers,
+                params,
+                output,
+            } => {
+                self.out.push_str(qualifiers);
+                self.out.push_str("fn(");
+                self.list(params, ", ");
+                self.out.push(')');
+                if let Some(output) = output {
+                    self.out.push_str(" -> ");
+                    self.name(output);
+                }
+            }
+            TypeName::Other(other) => self.out.push_str(other),
+        }
+    }
+}
+
+/// A system's name, which for a system made by combining others is made from theirs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SystemName {
+    Type(TypeName),
+    /// `a.pipe(b)`, named `Pipe(a, b)`
+    Pipe(Box<SystemName>, Box<SystemName>),
+    /// Conditions combined with `and_then`, named `a && b`
+    And(Box<SystemName>, Box<SystemName>),
+    /// `or_else`, named `a || b`
+    Or(Box<SystemName>, Box<SystemName>),
+    /// `not(a)`, named `!a`
+    Not(Box<SystemName>),
+}
+
+/// Where `operator` last appears outside of any brackets
+fn split_last<'a>(name: &'a str, operator: &str) -> Option<(&'a str, &'a str)> {
+    let mut depth = 0i32;
+    let mut split = None;
+    let bytes = name.as_bytes();
+    for (i, &c) in bytes.iter().enumerate() {
+        match c {
+            b'<' | b'(' | b'[' => depth += 1,
+            // Not the arrow in `fn() -> T`
+            b'>' if i > 0 && bytes[i - 1] == b'-' => {}
+            b'>' | b')' | b']' => depth -= 1,
+            _ if depth == 0 && name[i..].starts_with(operator) => split = Some(i),
+            _ => {}
         }
+    }
+    split.map(|i| (&name[..i], &name[i + operator.len()..]))
+}
+
+impl SystemName {
+    pub fn parse(full_name: &str) -> SystemName {
+        // Chained `and_then`s and `or_else`s, so the last one is outermost
+        let and = split_last(full_name, " && ");
+        let or = split_last(full_name, " || ");
+        let split = match (and, or) {
+            (Some(a), Some(o)) if a.0.len() > o.0.len() => Some((a, true)),
+            (_, Some(o)) => Some((o, false)),
+            (Some(a), None) => Some((a, true)),
+            (None, None) => None,
+        };
+        if let Some(((a, b), is_and)) = split {
+            let (a, b) = (Box::new(Self::parse(a)), Box::new(Self::parse(b)));
+            return if is_and {
+                SystemName::And(a, b)
+            } else {
+                SystemName::Or(a, b)
+            };
+        }
+        if let Some(inner) = full_name.strip_prefix('!') {
+            return SystemName::Not(Box::new(Self::parse(inner)));
+        }
+        let pipe = full_name
+            .strip_prefix("Pipe(")
+            .and_then(|rest| rest.strip_suffix(')'))
+            .and_then(|inner| split_last(inner, ", "));
+        match pipe {
+            Some((a, b)) => SystemName::Pipe(Box::new(Self::parse(a)), Box::new(Self::parse(b))),
+            None => SystemName::Type(TypeName::parse(full_name)),
+        }
+    }
+
+    /// The systems it's made of, or just itself
+    pub fn parts(&self) -> Vec<&TypeName> {
+        match self {
+            SystemName::Type(name) => vec![name],
+            SystemName::Pipe(a, b) | SystemName::And(a, b) | SystemName::Or(a, b) => {
+                let mut parts = a.parts();
+                parts.extend(b.parts());
+                parts
+            }
+            SystemName::Not(a) => a.parts(),
+        }
+    }
+
+    pub fn is_compound(&self) -> bool {
+        !matches!(self, SystemName::Type(_))
+    }
+
+    /// Every path in every part, indexed the same way as `print`
+    pub fn paths(&self) -> Vec<&[PathSegment]> {
+        self.parts().into_iter().flat_map(TypeName::paths).collect()
+    }
+
+    /// The crate of the first system in it
+    pub fn crate_name(&self) -> &str {
+        self.parts()[0].crate_name()
+    }
+
+    pub fn shortest(&self) -> String {
+        self.print(&|_, _| 1, false)
+    }
+
+    /// Like `TypeName::print`, with the parts joined as `a | b` for pipes
+    pub fn print(
+        &self,
+        depth: &dyn Fn(usize, &[PathSegment]) -> usize,
+        collapse_generics: bool,
+    ) -> String {
+        let mut printer = Printer::new(depth, collapse_generics);
+        self.print_parts(&mut printer);
+        printer.out
+    }
+
+    /// How tightly it binds when printed, like Rust's `||`, `&&`, `|` and `!`
+    fn precedence(&self) -> u8 {
+        match self {
+            SystemName::Or(..) => 1,
+            SystemName::And(..) => 2,
+            SystemName::Pipe(..) => 3,
+            SystemName::Not(_) => 4,
+            SystemName::Type(_) => 5,
+        }
+    }
+
+    fn print_parts(&self, printer: &mut Printer) {
+        let part = |part: &SystemName, brackets: bool, printer: &mut Printer| {
+            if brackets {
+                printer.out.push('(');
+            }
+            part.print_parts(printer);
+            if brackets {
+                printer.out.push(')');
+            }
+        };
+        match self {
+            SystemName::Type(name) => printer.name(name),
+            SystemName::Pipe(a, b) | SystemName::And(a, b) | SystemName::Or(a, b) => {
+                let operator = match self {
+                    SystemName::Pipe(..) => " | ",
+                    SystemName::And(..) => " && ",
+                    _ => " || ",
+                };
+                part(a, a.precedence() < self.precedence(), printer);
+                printer.out.push_str(operator);
+                part(b, b.precedence() <= self.precedence(), printer);
+            }
+            SystemName::Not(a) => {
+                printer.out.push('!');
+                part(a, a.precedence() < self.precedence(), printer);
+            }
+        }
+    }
+}
 
-        if func1name.ends_with(',') {
-            func1name.push(' ');
+/// The whole name, as bevy gives it
+impl fmt::Display for SystemName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SystemName::Type(name) => write!(f, "{}", name),
+            SystemName::Pipe(a, b) => write!(f, "Pipe({}, {})", a, b),
+            SystemName::And(a, b) => write!(f, "{} && {}", a, b),
+            SystemName::Or(a, b) => write!(f, "{} || {}", a, b),
+            SystemName::Not(a) => write!(f, "!{}", a),
         }
-        if func2name.ends_with(',') {
-            func2name.push(' ');
+    }
+}
+
+/// How systems are labelled in the graph (cycled with L)
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NameStyle {
+    /// `step`, with just enough of the path added back to tell it apart
+    #[default]
+    ShortestUnique,
+    /// `my_game::physics::step`
+    CrateQualified,
+    /// Exactly what `type_name` gives
+    Full,
+    /// `update_assets<..>`
+    CollapseGenerics,
+}
+
+impl NameStyle {
+    pub fn next(self) -> Self {
+        match self {
+            NameStyle::ShortestUnique => NameStyle::CrateQualified,
+            NameStyle::CrateQualified => NameStyle::Full,
+            NameStyle::Full => NameStyle::CollapseGenerics,
+            NameStyle::CollapseGenerics => NameStyle::ShortestUnique,
         }
     }
+}
 
-    (func1name, func2name)
+pub fn cycle_name_style(keys: Res<ButtonInput<KeyCode>>, mut style: ResMut<NameStyle>) {
+    if keys.just_pressed(KeyCode::KeyL) {
+        *style = style.next();
+    }
 }
 
-// TODO: Handle ambiguity
-pub fn shorten_systems(systems: HashMap<NodeId, Cow<str>>) -> HashMap<NodeId, String> {
-    systems
+/// Shortens each name as much as the style allows while keeping them all different, adding back
+/// leading path segments to whichever paths tell clashing names apart. Names that are still the
+/// same are numbered in the order the systems were added, which for closures in the same function
+/// is as `{closure#1}`, `{closure#2}`
+pub fn shorten_systems(
+    systems: HashMap<NodeId, Cow<str>>,
+    style: NameStyle,
+) -> HashMap<NodeId, String> {
+    let mut nodes: Vec<_> = systems.keys().copied().collect();
+    nodes.sort();
+    let names: Vec<_> = nodes
+        .iter()
+        .map(|n| SystemName::parse(&systems[n]))
+        .collect();
+    let paths: Vec<_> = names.iter().map(SystemName::paths).collect();
+    let outermost: Vec<Vec<usize>> = names
+        .iter()
+        .map(|name| {
+            let mut start = 0;
+            name.parts()
+                .into_iter()
+                .map(|part| {
+                    let index = start;
+                    start += part.paths().len();
+                    index
+                })
+                .collect()
+        })
+        .collect();
+    let mut depths: Vec<_> = paths.iter().map(|p| vec![1; p.len()]).collect();
+    let print = |i: usize, depths: &[Vec<usize>]| match style {
+        NameStyle::ShortestUnique => names[i].print(&|k, _| depths[i][k], false),
+        // The outermost path of each part in full
+        NameStyle::CrateQualified => names[i].print(
+            &|k, path| {
+                if outermost[i].contains(&k) {
+                    path.len()
+                } else {
+                    depths[i][k]
+                }
+            },
+            false,
+        ),
+        NameStyle::Full => systems[&nodes[i]].to_string(),
+        NameStyle::CollapseGenerics => names[i].print(&|k, _| depths[i][k], true),
+    };
+
+    loop {
+        // Full names can't get any longer
+        if style == NameStyle::Full {
+            break;
+        }
+        let shortened: Vec<_> = (0..names.len()).map(|i| print(i, &depths)).collect();
+        let mut clashes: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (i, short_name) in shortened.iter().enumerate() {
+            clashes.entry(short_name).or_default().push(i);
+        }
+
+        let mut lengthened = false;
+        for clash in clashes.values().filter(|clash| clash.len() > 1) {
+            for &i in clash {
+                // The first path that isn't the same in all of them and can still be longer
+                let path = (0..paths[i].len()).find(|&k| {
+                    depths[i][k] < named_len(paths[i][k])
+                        && clash.iter().any(|&j| paths[j].get(k) != Some(&paths[i][k]))
+                });
+                if let Some(k) = path {
+                    depths[i][k] += 1;
+                    lengthened = true;
+                }
+            }
+        }
+        if !lengthened {
+            break;
+        }
+    }
+
+    let shortened: Vec<_> = (0..names.len()).map(|i| print(i, &depths)).collect();
+    let mut totals: HashMap<&str, usize> = HashMap::new();
+    for short_name in &shortened {
+        *totals.entry(short_name).or_default() += 1;
+    }
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    nodes
         .iter()
-        .map(|(&n, s)| (n, FunctionName::new(s).shortest()))
+        .zip(&shortened)
+        .map(|(&node, short_name)| {
+            let count = seen.entry(short_name).or_default();
+            *count += 1;
+            let closure = short_name
+                .rfind(READABLE_CLOSURE)
+                .filter(|_| style != NameStyle::Full);
+            let numbered = match closure {
+                _ if totals[short_name.as_str()] == 1 => short_name.clone(),
+                Some(at) => format!(
+                    "{}{{closure#{}}}{}",
+                    &short_name[..at],
+                    count,
+                    &short_name[at + READABLE_CLOSURE.len()..]
+                ),
+                None if *count == 1 => short_name.clone(),
+                None => format!("{} ({})", short_name, count),
+            };
+            (node, numbered)
+        })
         .collect()
 }
+
+/// Labels a `SystemTypeSet` that has no systems in it from its `Debug`, which is
+/// `SystemTypeSet(fn FunctionSystem<Marker, F>())` with `F` the system's function
+pub fn system_type_set_name(full_name: &str) -> String {
+    let system = full_name
+        .strip_prefix("SystemTypeSet(fn ")
+        .and_then(|rest| rest.strip_suffix("())"))
+        .unwrap_or(full_name);
+    match TypeName::parse(system) {
+        TypeName::Path(path) => match &path.last().unwrap().generics {
+            Generics::Angle(generics) if !generics.is_empty() => {
+                generics.last().unwrap().shortest()
+            }
+            _ => TypeName::Path(path).shortest(),
+        },
+        other => other.shortest(),
+    }
+}
+
+/// Labels the sets bevy makes for `(a, b).run_if(..)` after the module their systems are in,
+/// numbered within it in the order they were made
+pub fn name_anonymous_sets(mut sets: Vec<(NodeId, Vec<&str>)>) -> HashMap<NodeId, String> {
+    sets.sort_by_key(|(node, _)| *node);
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    sets.into_iter()
+        .map(|(node, systems)| {
+            let modules: Vec<Vec<String>> = systems
+                .iter()
+                .map(|system| {
+                    let name = SystemName::parse(system);
+                    // Systems with their own names might not have a path in them
+                    let Some(path) = name
+                        .parts()
+                        .first()
+                        .and_then(|part| part.paths().first().copied())
+                    else {
+                        return Vec::new();
+                    };
+                    // Leave off the function itself
+                    let module = &path[..named_len(path).saturating_sub(1)];
+                    module.iter().map(|s| s.name.clone()).collect()
+                })
+                .collect();
+            let common = modules.first().map_or(0, |first| {
+                (0..first.len())
+                    .take_while(|&i| modules.iter().all(|m| m.get(i) == first.get(i)))
+                    .count()
+            });
+            let module = modules
+                .first()
+                .and_then(|first| first[..common].last())
+                .cloned()
+                .unwrap_or_default();
+            let count = counts.entry(module.clone()).or_default();
+            *count += 1;
+            let name = if module.is_empty() {
+                format!("run_if group #{}", count)
+            } else {
+                format!("run_if group #{} in {}", count, module)
+            };
+            (node, name)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(full_name: &str) -> TypeName {
+        let name = TypeName::parse(full_name);
+        assert_eq!(name.to_string(), full_name, "{:?}", name);
+        name
+    }
+
+    #[test]
+    fn paths() {
+        let name = round_trip("my_game::player::move_player");
+        assert_eq!(name.shortest(), "move_player");
+        assert_eq!(name.crate_name(), "my_game");
+
+        let name =
+            round_trip("bevy_ecs::event::event_update_system<bevy_input::keyboard::KeyboardInput>");
+        assert_eq!(name.shortest(), "event_update_system<KeyboardInput>");
+    }
+
+    #[test]
+    fn qualified_paths() {
+        let name = round_trip("<my_game::Player as bevy_ecs::component::Component>::Storage");
+        assert!(matches!(
+            name,
+            TypeName::Qualified {
+                as_trait: Some(_),
+                ..
+            }
+        ));
+        assert_eq!(name.shortest(), "<Player as Component>::Storage");
+
+        let name = round_trip("<my_game::Player>::update");
+        assert!(matches!(name, TypeName::Qualified { as_trait: None, .. }));
+    }
+
+    #[test]
+    fn trait_objects() {
+        let name = round_trip("alloc::boxed::Box<dyn core::any::Any + core::marker::Send>");
+        let TypeName::Path(path) = &name else {
+            panic!("{:?}", name);
+        };
+        let Generics::Angle(generics) = &path[2].generics else {
+            panic!("{:?}", path[2]);
+        };
+        assert!(matches!(&generics[..], [TypeName::Dyn(traits)] if traits.len() == 2));
+        assert_eq!(name.shortest(), "Box<dyn Any + Send>");
+    }
+
+    #[test]
+    fn references_and_collections() {
+        let name = round_trip("&mut [my_game::Player]");
+        assert!(matches!(
+            name,
+            TypeName::Reference { mutable: true, inner } if matches!(*inner, TypeName::Slice(_))
+        ));
+        let name = round_trip("&(my_game::Player, [f32; 3])");
+        let TypeName::Reference {
+            mutable: false,
+            inner,
+        } = &name
+        else {
+            panic!("{:?}", name);
+        };
+        assert!(matches!(
+            &**inner,
+            TypeName::Tuple(types) if matches!(&types[1], TypeName::Array(_, len) if len == "3")
+        ));
+        assert_eq!(name.shortest(), "&(Player, [f32; 3])");
+        round_trip("()");
+        round_trip("*const u8");
+    }
+
+    #[test]
+    fn fn_pointers() {
+        let name = round_trip("fn(my_game::Player) -> core::option::Option<u32>");
+        assert!(matches!(
+            name,
+            TypeName::FnPointer { ref params, output: Some(_), .. } if params.len() == 1
+        ));
+        assert_eq!(name.shortest(), "fn(Player) -> Option<u32>");
+        round_trip("unsafe extern \"C\" fn()");
+    }
+
+    #[test]
+    fn closures() {
+        let name = round_trip("my_game::setup::{{closure}}");
+        assert_eq!(name.shortest(), "setup::{closure}");
+        round_trip("my_game::setup::{{closure}}::{{closure}}");
+    }
+
+    fn shorten(full_names: &[&str], style: NameStyle) -> Vec<String> {
+        let systems = full_names
+            .iter()
+            .enumerate()
+            .map(|(i, &name)| (NodeId::System(i), Cow::from(name)))
+            .collect();
+        let mut names = shorten_systems(systems, style);
+        (0..full_names.len())
+            .map(|i| names.remove(&NodeId::System(i)).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn clashing_systems() {
+        let names = shorten(
+            &[
+                "my_game::physics::step",
+                "my_game::ai::step",
+                "my_game::spawn",
+            ],
+            NameStyle::ShortestUnique,
+        );
+        assert_eq!(names, ["physics::step", "ai::step", "spawn"]);
+
+        // Only the crates tell them apart
+        let names = shorten(
+            &["game_a::physics::step", "game_b::physics::step"],
+            NameStyle::ShortestUnique,
+        );
+        assert_eq!(names, ["game_a::physics::step", "game_b::physics::step"]);
+
+        let names = shorten(
+            &["my_game::physics::step", "my_game::ai::step"],
+            NameStyle::CrateQualified,
+        );
+        assert_eq!(names, ["my_game::physics::step", "my_game::ai::step"]);
+    }
+
+    #[test]
+    fn system_added_twice() {
+        let names = shorten(
+            &[
+                "my_game::physics::step",
+                "my_game::physics::step",
+                "my_game::spawn",
+            ],
+            NameStyle::ShortestUnique,
+        );
+        assert_eq!(names, ["step", "step (2)", "spawn"]);
+    }
+
+    #[test]
+    fn closures_in_one_function() {
+        let names = shorten(
+            &[
+                "my_game::setup::{{closure}}",
+                "my_game::setup::{{closure}}",
+                "my_game::spawn::{{closure}}",
+            ],
+            NameStyle::ShortestUnique,
+        );
+        assert_eq!(
+            names,
+            [
+                "setup::{closure#1}",
+                "setup::{closure#2}",
+                "spawn::{closure}"
+            ]
+        );
+
+        // Left as `type_name` gives them, so numbered like any other name
+        let names = shorten(
+            &["my_game::setup::{{closure}}", "my_game::setup::{{closure}}"],
+            NameStyle::Full,
+        );
+        assert_eq!(
+            names,
+            [
+                "my_game::setup::{{closure}}",
+                "my_game::setup::{{closure}} (2)"
+            ]
+        );
+    }
+
+    #[test]
+    fn other() {
+        assert_eq!(TypeName::parse("!"), TypeName::Other("!".to_string()));
+        // Anything left over means it wasn't a type name after all
+        let name = TypeName::parse("my_game::Player) extra");
+        assert_eq!(name, TypeName::Other("my_game::Player) extra".to_string()));
+        assert_eq!(name.to_string(), "my_game::Player) extra");
+    }
+
+    #[test]
+    fn empty_system_type_sets() {
+        fn move_player() {}
+        let set = format!("{:?}", IntoSystemSet::into_system_set(move_player));
+        assert_eq!(system_type_set_name(&set), "move_player");
+
+        assert_eq!(
+            system_type_set_name(
+                "SystemTypeSet(fn bevy_ecs::system::function_system::FunctionSystem<fn(), my_game::player::move_player<my_game::Player>>())"
+            ),
+            "move_player<Player>"
+        );
+        // Anything else is just shortened
+        assert_eq!(system_type_set_name("my_game::Stage::Early"), "Early");
+    }
+
+    fn anonymous_sets(sets: &[&[&str]]) -> Vec<String> {
+        let sets = sets
+            .iter()
+            .enumerate()
+            .map(|(i, systems)| (NodeId::Set(i), systems.to_vec()))
+            .collect();
+        let mut names = name_anonymous_sets(sets);
+        (0..names.len())
+            .map(|i| names.remove(&NodeId::Set(i)).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn run_if_groups() {
+        let names = anonymous_sets(&[
+            &["my_game::player::move_player", "my_game::player::jump"],
+            &["my_game::enemy::chase"],
+            &["my_game::player::animate"],
+            &["my_game::player::jump", "my_game::enemy::chase"],
+            &["my_game::setup", "other_crate::setup"],
+        ]);
+        assert_eq!(
+            names,
+            [
+                "run_if group #1 in player",
+                "run_if group #1 in enemy",
+                "run_if group #2 in player",
+                "run_if group #1 in my_game",
+                "run_if group #1",
+            ]
+        );
+    }
+
+    #[test]
+    fn run_if_groups_numbered_in_order() {
+        let sets = vec![
+            (NodeId::Set(7), vec!["my_game::player::jump"]),
+            (NodeId::Set(2), vec!["my_game::player::move_player"]),
+        ];
+        let names = name_anonymous_sets(sets);
+        assert_eq!(names[&NodeId::Set(2)], "run_if group #1 in player");
+        assert_eq!(names[&NodeId::Set(7)], "run_if group #2 in player");
+    }
+
+    #[test]
+    fn run_if_group_without_systems() {
+        let names = anonymous_sets(&[&[], &["my_game::player::jump"], &[]]);
+        assert_eq!(
+            names,
+            [
+                "run_if group #1",
+                "run_if group #1 in player",
+                "run_if group #2"
+            ]
+        );
+    }
+}
@@ -0,0 +1,461 @@
+use std::{any::type_name, collections::BTreeMap, marker::PhantomData};
+
+use bevy::{
+    app::{InternedAppLabel, StateTransition},
+    ecs::schedule::{InternedScheduleLabel, NodeId},
+    prelude::*,
+    render::camera::RenderTarget,
+    ui::TargetCamera,
+    window::WindowRef,
+};
+
+use crate::{
+    frame_runs::{track_frame_runs, ConditionResult, FrameRuns},
+    layer_graph::{DrawnNode, LayerGraph},
+    schedule_graph::{schedule_systems, ScheduleInfo},
+    schedule_order::ScheduleButton,
+    shorten_type::TypeName,
+};
+
+const BUTTON_COLOUR: Color = Color::rgb(0.35, 0.35, 0.45);
+const FILTER_COLOUR: Color = Color::rgb(0.2, 0.6, 0.6);
+const GRAPHED_COLOUR: Color = Color::rgb(0.3, 0.5, 0.9);
+
+/// Lists the `OnEnter`, `OnExit` and `OnTransition` schedules of `S` in the states view, and
+/// which systems gated by `in_state` run in each state
+///
+/// Bevy can't say which state type a schedule label belongs to without knowing the type, so every
+/// `States` type to show needs one of these
+pub struct StateGraphPlugin<S: States>(PhantomData<S>);
+
+impl<S: States> Default for StateGraphPlugin<S> {
+    fn default() -> Self {
+        StateGraphPlugin(PhantomData)
+    }
+}
+
+impl<S: States> Plugin for StateGraphPlugin<S> {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<StatesView>().add_systems(
+            Main,
+            snapshot_state::<S>
+                .after(find_state_types)
+                .after(track_frame_runs)
+                .before(Main::run_main),
+        );
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateVariant {
+    pub name: String,
+    /// Its schedules and how they are described
+    pub schedules: Vec<(String, InternedScheduleLabel)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateEntry {
+    pub type_name: String,
+    /// Whether it has a `StateGraphPlugin`, otherwise only its name is known
+    pub registered: bool,
+    pub current: Option<String>,
+    pub variants: Vec<StateVariant>,
+    /// Systems and sets in the graphed schedule gated by `in_state` of this type, with the states
+    /// they have been seen running in
+    pub gated: Vec<(NodeId, Vec<String>)>,
+}
+
+impl StateEntry {
+    fn new(type_name: String) -> Self {
+        StateEntry {
+            type_name,
+            registered: false,
+            current: None,
+            variants: Vec::new(),
+            gated: Vec::new(),
+        }
+    }
+}
+
+/// The window listing every state and its schedules, toggled with T
+#[derive(Resource, Debug, Default)]
+pub struct StatesView {
+    pub shown: bool,
+    pub states: Vec<StateEntry>,
+    /// The state (type name and variant) whose gated systems are highlighted in the graph
+    pub filter: Option<(String, String)>,
+    /// The drawn nodes gated by the filtered state that have been seen running in it
+    pub filtered: Vec<DrawnNode>,
+    window: Option<Entity>,
+    camera: Option<Entity>,
+    root: Option<Entity>,
+}
+
+impl StatesView {
+    fn entry_mut(&mut self, type_name: &str) -> &mut StateEntry {
+        let index = match self.states.iter().position(|s| s.type_name == type_name) {
+            Some(index) => index,
+            None => {
+                self.states.push(StateEntry::new(type_name.to_string()));
+                self.states.sort_by(|a, b| a.type_name.cmp(&b.type_name));
+                self.states
+                    .iter()
+                    .position(|s| s.type_name == type_name)
+                    .unwrap()
+            }
+        };
+        &mut self.states[index]
+    }
+}
+
+#[derive(Component)]
+pub struct StateFilterButton(pub String, pub String);
+
+pub fn toggle_states_view(keys: Res<ButtonInput<KeyCode>>, mut view: ResMut<StatesView>) {
+    if keys.just_pressed(KeyCode::KeyT) {
+        view.shown = !view.shown;
+    }
+}
+
+/// Finds every state type from the systems `init_state` adds, so the ones without a
+/// `StateGraphPlugin` can at least be listed. Runs in `Main` so `StateTransition` can be seen
+pub fn find_state_types(schedules: Res<Schedules>, mut view: ResMut<StatesView>) {
+    let Some(schedule) = schedules.get(StateTransition) else {
+        return;
+    };
+    for (_, system) in schedule_systems(schedule) {
+        let name = system.name();
+        let Some(state) = name
+            .strip_prefix("bevy_ecs::schedule::state::apply_state_transition<")
+            .and_then(|name| name.strip_suffix('>'))
+        else {
+            continue;
+        };
+        if !view.states.iter().any(|s| s.type_name == state) {
+            view.entry_mut(state);
+        }
+    }
+}
+
+fn snapshot_state<S: States>(
+    schedules: Res<Schedules>,
+    state: Option<Res<State<S>>>,
+    info: Res<ScheduleInfo>,
+    frame_runs: Res<FrameRuns>,
+    mut view: ResMut<StatesView>,
+    mut seen: Local<Option<(Option<InternedAppLabel>, InternedScheduleLabel)>>,
+    mut seen_in: Local<BTreeMap<NodeId, Vec<String>>>,
+) {
+    let current = state.map(|state| format!("{:?}", state.get()));
+
+    let mut variants: BTreeMap<String, Vec<(String, InternedScheduleLabel)>> = BTreeMap::new();
+    if let Some(current) = &current {
+        variants.entry(current.clone()).or_default();
+    }
+    for (_, schedule) in schedules.iter() {
+        let label = schedule.label();
+        let label_any = label.as_dyn_eq().as_any();
+        if let Some(OnEnter(state)) = label_any.downcast_ref::<OnEnter<S>>() {
+            let description = format!("OnEnter({:?})", state);
+            variants
+                .entry(format!("{:?}", state))
+                .or_default()
+                .push((description, label));
+        } else if let Some(OnExit(state)) = label_any.downcast_ref::<OnExit<S>>() {
+            let description = format!("OnExit({:?})", state);
+            variants
+                .entry(format!("{:?}", state))
+                .or_default()
+                .push((description, label));
+        } else if let Some(OnTransition { from, to }) = label_any.downcast_ref::<OnTransition<S>>()
+        {
+            let description = format!("OnTransition {{ {:?} -> {:?} }}", from, to);
+            for state in [from, to] {
+                variants
+                    .entry(format!("{:?}", state))
+                    .or_default()
+                    .push((description.clone(), label));
+            }
+        }
+    }
+    let variants = variants
+        .into_iter()
+        .map(|(name, mut schedules)| {
+            schedules.sort_by_key(|(description, _)| {
+                (!description.starts_with("OnEnter"), description.clone())
+            });
+            StateVariant { name, schedules }
+        })
+        .collect();
+
+    // `in_state` conditions are closures that can't be asked which state they want, so remember
+    // the states they were true in instead
+    if *seen != Some((info.app, info.schedule)) {
+        *seen = Some((info.app, info.schedule));
+        seen_in.clear();
+    }
+    let in_state = format!("in_state<{}>", type_name::<S>());
+    for (&node, conditions) in &info.conditions {
+        if !conditions.iter().any(|c| c.contains(&in_state)) {
+            continue;
+        }
+        let states = seen_in.entry(node).or_default();
+        let ran = frame_runs.conditions.get(&node).is_some_and(|results| {
+            results
+                .iter()
+                .any(|(c, result)| c.contains(&in_state) && *result == ConditionResult::True)
+        });
+        if let Some(current) = current.as_ref().filter(|c| ran && !states.contains(c)) {
+            states.push(current.clone());
+            states.sort();
+        }
+    }
+
+    let entry = StateEntry {
+        type_name: type_name::<S>().to_string(),
+        registered: true,
+        current,
+        variants,
+        gated: seen_in.iter().map(|(&n, s)| (n, s.clone())).collect(),
+    };
+    if view.states.iter().find(|s| s.type_name == entry.type_name) != Some(&entry) {
+        *view.entry_mut(type_name::<S>()) = entry;
+    }
+}
+
+pub fn select_state_filter(
+    buttons: Query<(&Interaction, &StateFilterButton), Changed<Interaction>>,
+    mut view: ResMut<StatesView>,
+) {
+    for (interaction, button) in &buttons {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+
+        // Clicking the filtered state again turns the filter off
+        let filter = (button.0.clone(), button.1.clone());
+        view.filter = if view.filter.as_ref() == Some(&filter) {
+            None
+        } else {
+            Some(filter)
+        };
+    }
+}
+
+pub fn filter_state_nodes(
+    mut view: ResMut<StatesView>,
+    layer_graph: Res<LayerGraph>,
+    info: Res<ScheduleInfo>,
+) {
+    if !view.is_changed() && !layer_graph.is_changed() {
+        return;
+    }
+
+    let mut filtered = Vec::new();
+    if let Some((type_name, variant)) = &view.filter {
+        let gated = view
+            .states
+            .iter()
+            .filter(|s| s.type_name == *type_name)
+            .flat_map(|s| &s.gated);
+        for (node, states) in gated {
+            if states.contains(variant) {
+                filtered.extend(layer_graph.displayed_as(&info, *node));
+            }
+        }
+    }
+    if view.filtered != filtered {
+        view.filtered = filtered;
+    }
+}
+
+pub fn draw_states_view(
+    mut commands: Commands,
+    mut view: ResMut<StatesView>,
+    info: Res<ScheduleInfo>,
+) {
+    if !view.is_changed() && !info.is_changed() {
+        return;
+    }
+    let view = view.bypass_change_detection();
+
+    if let Some(root) = view.root.take() {
+        commands.entity(root).despawn_recursive();
+    }
+    if !view.shown {
+        for entity in [view.camera.take(), view.window.take()]
+            .into_iter()
+            .flatten()
+        {
+            commands.entity(entity).despawn_recursive();
+        }
+        return;
+    }
+
+    let camera = match view.camera {
+        Some(camera) => camera,
+        None => {
+            let window = commands
+                .spawn(Window {
+                    title: "States".to_string(),
+                    ..default()
+                })
+                .id();
+            let camera = commands
+                .spawn(Camera2dBundle {
+                    camera: Camera {
+                        target: RenderTarget::Window(WindowRef::Entity(window)),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .id();
+            view.window = Some(window);
+            view.camera = Some(camera);
+            camera
+        }
+    };
+
+    let text_style = |font_size| TextStyle {
+        font_size,
+        color: Color::WHITE,
+        ..default()
+    };
+    let button = |colour: Color| NodeBundle {
+        style: Style {
+            padding: UiRect::axes(Val::Px(5.0), Val::Px(1.0)),
+            ..default()
+        },
+        background_color: colour.into(),
+        ..default()
+    };
+    let root = commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Column,
+                    padding: UiRect::all(Val::Px(10.0)),
+                    row_gap: Val::Px(5.0),
+                    ..default()
+                },
+                ..default()
+            },
+            TargetCamera(camera),
+        ))
+        .with_children(|builder| {
+            builder.spawn(TextBundle::from_section(
+                "Click a schedule to graph it, or a state to highlight the systems that ran in it \
+                 (T to close)",
+                text_style(20.0),
+            ));
+            if view.states.is_empty() {
+                builder.spawn(TextBundle::from_section("No states", text_style(18.0)));
+            }
+            for state in &view.states {
+                let name = TypeName::parse(&state.type_name).shortest();
+                let title = match (&state.current, state.registered) {
+                    (_, false) => format!(
+                        "{name} (add StateGraphPlugin::<{name}>::default() to see its schedules)"
+                    ),
+                    (Some(current), true) => format!("{name}, currently {current}"),
+                    (None, true) => name.clone(),
+                };
+                builder.spawn(TextBundle::from_section(title, text_style(18.0)));
+
+                for variant in &state.variants {
+                    builder
+                        .spawn(NodeBundle {
+                            style: Style {
+                                flex_wrap: FlexWrap::Wrap,
+                                align_items: AlignItems::Center,
+                                column_gap: Val::Px(5.0),
+                                row_gap: Val::Px(5.0),
+                                margin: UiRect::left(Val::Px(20.0)),
+                                ..default()
+                            },
+                            ..default()
+                        })
+                        .with_children(|builder| {
+                            let filter = (state.type_name.clone(), variant.name.clone());
+                            let colour = if view.filter.as_ref() == Some(&filter) {
+                                FILTER_COLOUR
+                            } else {
+                                BUTTON_COLOUR
+                            };
+                            builder
+                                .spawn((
+                                    button(colour),
+                                    Interaction::default(),
+                                    StateFilterButton(filter.0, filter.1),
+                                ))
+                                .with_children(|builder| {
+                                    builder.spawn(TextBundle::from_section(
+                                        &variant.name,
+                                        text_style(18.0),
+                                    ));
+                                });
+                            builder.spawn(TextBundle::from_section(":", text_style(18.0)));
+
+                            for (description, label) in &variant.schedules {
+                                let colour = if (None, *label) == (info.app, info.schedule) {
+                                    GRAPHED_COLOUR
+                                } else {
+                                    BUTTON_COLOUR
+                                };
+                                builder
+                                    .spawn((
+                                        button(colour),
+                                        Interaction::default(),
+                                        ScheduleButton(None, *label),
+                                    ))
+                                    .with_children(|builder| {
+                                        builder.spawn(TextBundle::from_section(
+                                            description,
+                                            text_style(18.0),
+                                        ));
+                                    });
+                            }
+
+                            let ran: Vec<_> = state
+                                .gated
+                                .iter()
+                                .filter(|(_, states)| states.contains(&variant.name))
+                                .map(|(node, _)| info.name(*node))
+                                .collect();
+                            if !ran.is_empty() {
+                                builder.spawn(TextBundle::from_section(
+                                    format!("in_state ran in {}: {}", info.label, ran.join(", ")),
+                                    text_style(18.0),
+                                ));
+                            }
+                        });
+                }
+
+                let not_seen: Vec<_> = state
+                    .gated
+                    .iter()
+                    .filter(|(_, states)| states.is_empty())
+                    .map(|(node, _)| info.name(*node))
+                    .collect();
+                if !not_seen.is_empty() {
+                    builder.spawn(TextBundle {
+                        text: Text::from_section(
+                            format!(
+                                "Gated by in_state in {} but not seen running yet: {}",
+                                info.label,
+                                not_seen.join(", ")
+                            ),
+                            text_style(18.0),
+                        ),
+                        style: Style {
+                            margin: UiRect::left(Val::Px(20.0)),
+                            ..default()
+                        },
+                        ..default()
+                    });
+                }
+            }
+        })
+        .id();
+    view.root = Some(root);
+}
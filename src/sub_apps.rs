@@ -0,0 +1,220 @@
+use std::sync::{Arc, Mutex};
+
+use bevy::{
+    app::{InternedAppLabel, SubApp},
+    ecs::schedule::{InternedScheduleLabel, NodeId, ScheduleLabel},
+    prelude::*,
+    render::ExtractSchedule,
+    utils::HashMap,
+};
+
+use crate::{
+    graph_refresh::ScheduleSignature,
+    layer_graph::LayerGraph,
+    schedule_graph::{schedule_conditions, schedule_graph_layout, LayoutSettings, ScheduleInfo},
+};
+
+/// What was last seen of a sub-app's schedules, as the main world can't look at them
+#[derive(Debug)]
+pub struct SubAppSnapshot {
+    pub main_schedule: InternedScheduleLabel,
+    /// How many systems each schedule has, `None` until bevy has built it
+    pub schedules: HashMap<InternedScheduleLabel, Option<usize>>,
+    /// The schedule the main world wants laid out, and how
+    pub requested: Option<InternedScheduleLabel>,
+    pub settings: LayoutSettings,
+    pub graph: Option<(ScheduleSignature, LayerGraph, ScheduleInfo)>,
+    /// The last schedule that couldn't be laid out, so it isn't tried again until it changes
+    pub failed: Option<(ScheduleSignature, InternedScheduleLabel, LayoutSettings)>,
+    /// Run conditions seen before bevy built each schedule (and moved them out of the graph)
+    pub conditions: HashMap<InternedScheduleLabel, HashMap<NodeId, Vec<String>>>,
+}
+
+impl SubAppSnapshot {
+    /// The schedules in roughly the order they run, extracting from the main world first
+    pub fn ordered_schedules(&self) -> Vec<InternedScheduleLabel> {
+        let mut schedules: Vec<_> = self.schedules.keys().copied().collect();
+        schedules.sort_by_key(|&label| {
+            (
+                label != ExtractSchedule.intern(),
+                label != self.main_schedule,
+                format!("{:?}", label),
+            )
+        });
+        schedules
+    }
+}
+
+/// The sub-apps the graph can show, in the main world
+#[derive(Resource, Default)]
+pub struct SubAppGraphs(pub Vec<(InternedAppLabel, Arc<Mutex<SubAppSnapshot>>)>);
+
+impl SubAppGraphs {
+    pub fn get(&self, app: InternedAppLabel) -> Option<&Arc<Mutex<SubAppSnapshot>>> {
+        self.0.iter().find(|(a, _)| *a == app).map(|(_, s)| s)
+    }
+}
+
+/// Reads the sub-app's world between its runs, when every one of its schedules is in it
+fn snapshot_sub_app(world: &World, snapshot: &Mutex<SubAppSnapshot>) {
+    let Some(schedules) = world.get_resource::<Schedules>() else {
+        return;
+    };
+    let mut snapshot = snapshot.lock().unwrap();
+    let snapshot = &mut *snapshot;
+    for (_, schedule) in schedules.iter() {
+        let built = schedule.systems().is_ok();
+        snapshot
+            .schedules
+            .insert(schedule.label(), built.then(|| schedule.systems_len()));
+        if !built {
+            snapshot
+                .conditions
+                .insert(schedule.label(), schedule_conditions(schedule));
+        }
+    }
+
+    let Some(schedule) = snapshot.requested.and_then(|label| schedules.get(label)) else {
+        return;
+    };
+    let signature = ScheduleSignature::new(Some(schedule));
+    let current = snapshot
+        .graph
+        .as_ref()
+        .map(|(signature, _, info)| (*signature, info.schedule));
+    if schedule.systems().is_err()
+        || current == Some((signature, schedule.label()))
+            && snapshot
+                .graph
+                .as_ref()
+                .is_some_and(|(_, _, info)| info.settings == snapshot.settings)
+    {
+        return;
+    }
+
+    let attempt = (signature, schedule.label(), snapshot.settings.clone());
+    if snapshot.failed.as_ref() == Some(&attempt) {
+        return;
+    }
+
+    match schedule_graph_layout(schedule, &snapshot.settings) {
+        Ok((layer_graph, mut info)) => {
+            if let Some(conditions) = snapshot.conditions.get(&schedule.label()) {
+                info.conditions.extend(conditions.clone());
+            }
+            snapshot.graph = Some((signature, layer_graph, info));
+        }
+        Err(e) => {
+            error!("Couldn't lay out {:?}: {e}", schedule.label());
+            snapshot.failed = Some(attempt);
+        }
+    }
+}
+
+/// Lets the graph show the schedules of the given sub-apps, warning about any the app doesn't have
+pub fn add_sub_app_snapshots(app: &mut App, labels: &[InternedAppLabel]) {
+    let mut graphs = Vec::new();
+    for &label in labels {
+        let Ok(sub_app) = app.get_sub_app(label) else {
+            warn!("There's no {:?} sub-app to graph", label);
+            continue;
+        };
+
+        let main_schedule = sub_app.main_schedule_label;
+        let schedules = sub_app.world.resource::<Schedules>();
+        let snapshot = SubAppSnapshot {
+            main_schedule,
+            schedules: schedules.iter().map(|(_, s)| (s.label(), None)).collect(),
+            requested: None,
+            settings: LayoutSettings::default(),
+            graph: None,
+            failed: None,
+            conditions: schedules
+                .iter()
+                .map(|(_, s)| (s.label(), schedule_conditions(s)))
+                .collect(),
+        };
+        let handle = Arc::new(Mutex::new(snapshot));
+
+        // Nothing is added to the sub-app itself, its extract function is wrapped to look at it
+        // once bevy's extract has run
+        let mut inner = app.remove_sub_app(label).unwrap();
+        let extract_snapshot = handle.clone();
+        let real_app = std::mem::replace(&mut inner.app, App::empty());
+        let inner = Mutex::new(inner);
+        let sub_app = SubApp::new(real_app, move |main_world, sub_app| {
+            let mut inner = inner.lock().unwrap();
+            std::mem::swap(&mut inner.app, sub_app);
+            inner.extract(main_world);
+            std::mem::swap(&mut inner.app, sub_app);
+            snapshot_sub_app(&sub_app.world, &extract_snapshot);
+        });
+        app.insert_sub_app(label, sub_app);
+        graphs.push((label, handle));
+    }
+    app.insert_resource(SubAppGraphs(graphs));
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::app::AppLabel;
+
+    use super::*;
+
+    #[derive(AppLabel, Debug, Clone, PartialEq, Eq, Hash)]
+    struct DummyApp;
+
+    #[derive(AppLabel, Debug, Clone, PartialEq, Eq, Hash)]
+    struct MissingApp;
+
+    #[derive(Resource)]
+    struct Extracted;
+
+    fn first() {}
+    fn second() {}
+
+    #[test]
+    fn snapshot() {
+        let mut app = App::new();
+        let mut sub_app = App::new();
+        sub_app.add_systems(Update, (first, second));
+        app.insert_sub_app(
+            DummyApp,
+            SubApp::new(sub_app, |_, sub_app| {
+                sub_app.world.insert_resource(Extracted);
+            }),
+        );
+        add_sub_app_snapshots(&mut app, &[DummyApp.intern(), MissingApp.intern()]);
+
+        let graphs = app.world.resource::<SubAppGraphs>();
+        assert_eq!(graphs.0.len(), 1);
+        let snapshot = graphs.get(DummyApp.intern()).unwrap().clone();
+        {
+            let mut snapshot = snapshot.lock().unwrap();
+            assert_eq!(snapshot.main_schedule, Main.intern());
+            assert_eq!(snapshot.schedules[&Update.intern()], None);
+            assert_eq!(snapshot.ordered_schedules()[0], Main.intern());
+            snapshot.requested = Some(Update.intern());
+        }
+
+        // The schedules are only built once the sub-app has run, after the first extract
+        app.update();
+        assert!(snapshot.lock().unwrap().graph.is_none());
+        app.update();
+        // Its own extract still runs
+        assert!(app.sub_app(DummyApp).world.contains_resource::<Extracted>());
+
+        let snapshot = snapshot.lock().unwrap();
+        assert_eq!(snapshot.schedules[&Update.intern()], Some(2));
+        let (_, _, info) = snapshot.graph.as_ref().unwrap();
+        assert_eq!(info.schedule, Update.intern());
+        let mut systems: Vec<_> = info
+            .full_names
+            .iter()
+            .filter(|(node, _)| node.is_system())
+            .map(|(_, name)| name.rsplit("::").next().unwrap())
+            .collect();
+        systems.sort();
+        assert_eq!(systems, ["first", "second"]);
+    }
+}
@@ -0,0 +1,296 @@
+use std::{
+    cell::RefCell,
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    log::{
+        tracing_subscriber::{layer::Context, Layer},
+        BoxedSubscriber,
+    },
+    prelude::*,
+    utils::{
+        tracing::{
+            field::{Field, Visit},
+            span::{Attributes, Id},
+            Subscriber,
+        },
+        HashMap,
+    },
+};
+
+/// One run of a system (or run condition), as seen by its `bevy/trace` span
+#[derive(Debug, Clone)]
+pub struct SystemRun {
+    /// The innermost schedule being run at the time, `None` if it couldn't be worked out
+    pub schedule: Option<Arc<str>>,
+    pub name: Arc<str>,
+    pub thread: Arc<str>,
+    pub start: Instant,
+    pub end: Instant,
+}
+
+impl SystemRun {
+    pub fn duration(&self) -> Duration {
+        self.end - self.start
+    }
+}
+
+// What we care about for each span, the rest are ignored
+#[derive(Clone)]
+enum TracedSpan {
+    Schedule(Arc<str>),
+    System(Arc<str>),
+    // Spans like `system_task` that run on another thread on behalf of a schedule
+    InSchedule(Arc<str>),
+}
+
+static RUNS: Mutex<Vec<SystemRun>> = Mutex::new(Vec::new());
+static SCHEDULE_RUNS: Mutex<Vec<Arc<str>>> = Mutex::new(Vec::new());
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+fn spans() -> &'static Mutex<HashMap<Id, TracedSpan>> {
+    static SPANS: OnceLock<Mutex<HashMap<Id, TracedSpan>>> = OnceLock::new();
+    SPANS.get_or_init(Default::default)
+}
+
+thread_local! {
+    static SCHEDULE_STACK: RefCell<Vec<Arc<str>>> = const { RefCell::new(Vec::new()) };
+    static STARTS: RefCell<HashMap<Id, Instant>> = RefCell::new(HashMap::new());
+}
+
+/// Takes every system run recorded since the last call
+pub fn take_system_runs() -> Vec<SystemRun> {
+    std::mem::take(&mut *RUNS.lock().unwrap())
+}
+
+/// Takes the label of every schedule run since the last call, once per run
+pub fn take_schedule_runs() -> Vec<Arc<str>> {
+    std::mem::take(&mut *SCHEDULE_RUNS.lock().unwrap())
+}
+
+#[derive(Default)]
+struct NameVisitor(Option<String>);
+
+impl Visit for NameVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "name" {
+            self.0 = Some(value.to_string());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "name" {
+            let name = format!("{:?}", value);
+            // Strings are debug printed with quotes
+            self.0 = Some(name.trim_matches('"').to_string());
+        }
+    }
+}
+
+/// Records the spans bevy's executors make when the `trace` feature is enabled
+pub struct SystemTimingLayer;
+
+impl<S: Subscriber> Layer<S> for SystemTimingLayer {
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut spans = spans().lock().unwrap();
+        let span = match attrs.metadata().name() {
+            kind @ ("schedule" | "system") => {
+                let mut visitor = NameVisitor::default();
+                attrs.record(&mut visitor);
+                let Some(name) = visitor.0 else {
+                    return;
+                };
+                if kind == "schedule" {
+                    TracedSpan::Schedule(name.into())
+                } else {
+                    TracedSpan::System(name.into())
+                }
+            }
+            _ => {
+                let parent = if attrs.is_contextual() {
+                    ctx.current_span().id().cloned()
+                } else {
+                    attrs.parent().cloned()
+                };
+                match parent.and_then(|parent| spans.get(&parent).cloned()) {
+                    Some(TracedSpan::Schedule(label) | TracedSpan::InSchedule(label)) => {
+                        TracedSpan::InSchedule(label)
+                    }
+                    _ => return,
+                }
+            }
+        };
+        spans.insert(id.clone(), span);
+    }
+
+    fn on_enter(&self, id: &Id, _ctx: Context<'_, S>) {
+        let span = spans().lock().unwrap().get(id).cloned();
+        match span {
+            Some(TracedSpan::Schedule(label)) => {
+                SCHEDULE_RUNS.lock().unwrap().push(label.clone());
+                SCHEDULE_STACK.with_borrow_mut(|stack| stack.push(label));
+            }
+            Some(TracedSpan::InSchedule(label)) => {
+                SCHEDULE_STACK.with_borrow_mut(|stack| stack.push(label));
+            }
+            Some(TracedSpan::System(_)) => {
+                STARTS.with_borrow_mut(|starts| starts.insert(id.clone(), Instant::now()));
+            }
+            None => {}
+        }
+    }
+
+    fn on_exit(&self, id: &Id, _ctx: Context<'_, S>) {
+        let span = spans().lock().unwrap().get(id).cloned();
+        match span {
+            Some(TracedSpan::Schedule(_) | TracedSpan::InSchedule(_)) => {
+                SCHEDULE_STACK.with_borrow_mut(|stack| stack.pop());
+            }
+            Some(TracedSpan::System(name)) => {
+                let end = Instant::now();
+                let Some(start) = STARTS.with_borrow_mut(|starts| starts.remove(id)) else {
+                    return;
+                };
+                let current = std::thread::current();
+                let thread = match current.name() {
+                    Some(name) => name.into(),
+                    None => format!("{:?}", current.id()).into(),
+                };
+                RUNS.lock().unwrap().push(SystemRun {
+                    schedule: SCHEDULE_STACK.with_borrow(|stack| stack.last().cloned()),
+                    name,
+                    thread,
+                    start,
+                    end,
+                });
+            }
+            None => {}
+        }
+    }
+
+    fn on_close(&self, id: Id, _ctx: Context<'_, S>) {
+        spans().lock().unwrap().remove(&id);
+    }
+}
+
+/// Pass this as `LogPlugin::update_subscriber` to record system timings
+/// (they only exist with bevy's `trace` feature, enabled by this crate's `trace` feature)
+pub fn timing_layer(subscriber: BoxedSubscriber) -> BoxedSubscriber {
+    INSTALLED.store(true, Ordering::Relaxed);
+    Box::new(SystemTimingLayer.with_subscriber(subscriber))
+}
+
+pub fn timing_layer_installed() -> bool {
+    INSTALLED.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemTiming {
+    pub average_ms: f64,
+    pub runs_per_frame: f64,
+}
+
+impl SystemTiming {
+    pub fn ms_per_frame(&self) -> f64 {
+        self.average_ms * self.runs_per_frame
+    }
+}
+
+/// Averaged timings, keyed by schedule and full system name, updated every
+/// `TimingSettings::update_every` frames
+#[derive(Resource, Debug, Default)]
+pub struct SystemTimings(pub HashMap<(String, String), SystemTiming>);
+
+impl SystemTimings {
+    pub fn get(&self, schedule: &str, system: &str) -> Option<&SystemTiming> {
+        self.0.get(&(schedule.to_string(), system.to_string()))
+    }
+}
+
+#[derive(Resource, Debug)]
+pub struct TimingSettings {
+    pub update_every: u32,
+    /// Stop updating the timings (toggle with F)
+    pub frozen: bool,
+}
+
+impl Default for TimingSettings {
+    fn default() -> Self {
+        TimingSettings {
+            update_every: 30,
+            frozen: false,
+        }
+    }
+}
+
+/// The runs since the last time the averages were updated
+#[derive(Resource, Default)]
+pub struct RecentSystemRuns {
+    frames: u32,
+    /// This frame's runs, for anything that wants every run
+    pub last_frame: Vec<SystemRun>,
+    /// How many times each schedule ran this frame
+    pub last_frame_schedules: HashMap<Arc<str>, u32>,
+    totals: HashMap<(Arc<str>, Arc<str>), (Duration, u32)>,
+}
+
+pub fn freeze_system_timings(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut settings: ResMut<TimingSettings>,
+) {
+    if keys.just_pressed(KeyCode::KeyF) {
+        settings.frozen = !settings.frozen;
+    }
+}
+
+pub fn collect_system_timings(
+    settings: Res<TimingSettings>,
+    mut recent: ResMut<RecentSystemRuns>,
+    mut timings: ResMut<SystemTimings>,
+) {
+    let runs = take_system_runs();
+    let schedule_runs = take_schedule_runs();
+    if settings.frozen {
+        return;
+    }
+
+    for run in &runs {
+        let Some(schedule) = &run.schedule else {
+            continue;
+        };
+        let total = recent
+            .totals
+            .entry((schedule.clone(), run.name.clone()))
+            .or_default();
+        total.0 += run.duration();
+        total.1 += 1;
+    }
+    recent.last_frame = runs;
+    recent.last_frame_schedules.clear();
+    for schedule in schedule_runs {
+        *recent.last_frame_schedules.entry(schedule).or_default() += 1;
+    }
+    recent.frames += 1;
+
+    if recent.frames >= settings.update_every.max(1) {
+        let frames = recent.frames as f64;
+        timings.0 = recent
+            .totals
+            .drain()
+            .map(|((schedule, name), (total, runs))| {
+                let timing = SystemTiming {
+                    average_ms: total.as_secs_f64() * 1000.0 / runs as f64,
+                    runs_per_frame: runs as f64 / frames,
+                };
+                ((schedule.to_string(), name.to_string()), timing)
+            })
+            .collect();
+        recent.frames = 0;
+    }
+}